@@ -36,11 +36,11 @@ fn get_input() -> Result<String, i32> {
 /// e.g.
 /// `assert_eq!(calculate_sum(&String::from("a22")), 2);`
 /// `assert_eq!(calculate_sum(&String::from("2a2")), 0);`
-fn calculate_sum(s: &String) -> u32 {
+fn calculate_sum(s: &str) -> u32 {
     s.chars().zip(s.chars().skip(1))    // Zip pairs of consecutive chars
         .chain(s.chars().zip(s.chars().rev().take(1))) // Append (last,first)
         .filter(|&(first, second)| {    // Only including matching digits
-            first.is_digit(10) && second.is_digit(10) && first == second
+            first.is_ascii_digit() && second.is_ascii_digit() && first == second
         })
         .fold(0, |acc, (x, _)| {        // Sum it all up
             acc + x.to_digit(10).unwrap()
@@ -74,7 +74,7 @@ mod tests {
 
     #[test]
     fn empty() {
-        assert_eq!(calculate_sum(&String::from("")), 0);
+        assert_eq!(calculate_sum(""), 0);
     }
 
     #[test]
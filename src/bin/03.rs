@@ -4,7 +4,7 @@ fn spiral_coords(value: u32) -> (i64, i64) {
     // For loop n of the spiral, the highest number (N) is given by
     // (2n + 1)^2
     let n = (((value as f64).sqrt() - 1.0) / 2.0).ceil() as i64;
-    let max = (2 * n + 1).pow(2) as i64;
+    let max = (2 * n + 1).pow(2);
 
     // All numers in loop n fall on a square between
     // (n,n), (-n,n), (n,-n) and (-n,-n). We can determine
@@ -1,61 +1,1154 @@
-use std::collections::HashSet;
+extern crate rand;
+
+use rand::{Rng, SeedableRng, StdRng};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::env::args;
+use std::fs::File;
+use std::hash::Hash;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
 use std::process::exit;
 
-/// Checks if a string slice is a valid passphrase (i.e. no repeated words)
-fn is_valid(passphrase: &str) -> bool {
-    // HashSet::insert() returns true if the inserted value isn't already
-    // in the set, so checking that all words can be inserted without a
-    // false return is an easy check for repeats.
-    let mut word_map = HashSet::new();
-    passphrase
-        .split_whitespace()
-        .all(|word| word_map.insert(word))
+/// A single house rule a passphrase has to satisfy
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rule {
+    /// No word may appear more than once (part 1)
+    NoDuplicates,
+    /// No word may be an anagram of another word (part 2)
+    NoAnagrams,
+    /// No word may appear more than once, ignoring case
+    NoCaseDuplicates,
+    /// The passphrase must have at least this many words
+    MinWords(usize),
+    /// Every word must have at least this many characters
+    MinLength(usize),
+    /// No two words may be within this edit distance of each other
+    EditDistance(usize),
+}
+
+impl Rule {
+    /// Builds a rule from its name and (for rules that take one) its
+    /// numeric argument. Names match the CLI flags without the `--`.
+    fn from_spec(name: &str, arg: Option<&str>) -> Result<Rule, String> {
+        let number = || match arg {
+            Some(arg) => arg
+                .parse::<usize>()
+                .map_err(|_| format!("'{}' expects a number, got '{}'", name, arg)),
+            None => Err(format!("'{}' expects a number", name)),
+        };
+        let no_arg = |rule| match arg {
+            Some(arg) => Err(format!("'{}' takes no argument, got '{}'", name, arg)),
+            None => Ok(rule),
+        };
+
+        match name {
+            "no-duplicates" => no_arg(Rule::NoDuplicates),
+            "no-anagrams" => no_arg(Rule::NoAnagrams),
+            "no-case-duplicates" => no_arg(Rule::NoCaseDuplicates),
+            "min-words" => number().map(Rule::MinWords),
+            "min-length" => number().map(Rule::MinLength),
+            "edit-distance" => number().map(Rule::EditDistance),
+            _ => Err(format!("Unknown rule '{}'", name)),
+        }
+    }
+
+    /// Whether the rule is followed by a numeric argument
+    fn takes_arg(name: &str) -> bool {
+        name == "min-words" || name == "min-length" || name == "edit-distance"
+    }
+
+    /// Name of the rule (including its argument) used in diagnostics
+    fn name(&self) -> String {
+        match *self {
+            Rule::NoDuplicates => String::from("no-duplicates"),
+            Rule::NoAnagrams => String::from("no-anagrams"),
+            Rule::NoCaseDuplicates => String::from("no-case-duplicates"),
+            Rule::MinWords(n) => format!("min-words {}", n),
+            Rule::MinLength(n) => format!("min-length {}", n),
+            Rule::EditDistance(n) => format!("edit-distance {}", n),
+        }
+    }
+
+    /// Checks the words of a single passphrase against this rule
+    fn allows(&self, words: &[&str]) -> bool {
+        self.conflicts(words).is_none()
+    }
+
+    /// Checks the words of a single passphrase against this rule. Returns
+    /// None if the rule holds, or the (0-based) positions of the words
+    /// that break it otherwise. Rules that apply to the passphrase as a
+    /// whole (e.g. min-words) break it without any conflicting words.
+    fn conflicts(&self, words: &[&str]) -> Option<Vec<usize>> {
+        match *self {
+            Rule::NoDuplicates => first_repeat(words.iter().cloned()),
+            Rule::NoAnagrams => first_repeat(words.iter().map(|word| anagram_key(word))),
+            Rule::NoCaseDuplicates => first_repeat(words.iter().map(|word| word.to_lowercase())),
+            Rule::MinWords(n) => {
+                if words.len() < n {
+                    Some(vec![])
+                } else {
+                    None
+                }
+            }
+            Rule::MinLength(n) => {
+                let short: Vec<usize> = (0..words.len())
+                    .filter(|&i| words[i].chars().count() < n)
+                    .collect();
+                if short.is_empty() {
+                    None
+                } else {
+                    Some(short)
+                }
+            }
+            Rule::EditDistance(n) => (0..words.len())
+                .flat_map(|i| (i + 1..words.len()).map(move |j| (i, j)))
+                .find(|&(i, j)| edit_distance(words[i], words[j]) <= n)
+                .map(|(i, j)| vec![i, j]),
+        }
+    }
+}
+
+/// Finds the first key that has already been seen, returning the positions
+/// of both occurrences.
+fn first_repeat<K, I>(keys: I) -> Option<Vec<usize>>
+where
+    K: Hash + Eq,
+    I: Iterator<Item = K>,
+{
+    // HashMap::insert() returns the previous value if the key was already
+    // in the map, which is the position of the word being repeated.
+    let mut seen = HashMap::new();
+    keys.enumerate()
+        .filter_map(|(i, key)| seen.insert(key, i).map(|first| vec![first, i]))
+        .next()
+}
+
+/// A passphrase that was rejected by a policy
+#[derive(Debug, PartialEq)]
+struct Rejection {
+    /// Line number of the passphrase (1-based)
+    line: usize,
+    /// Name of the rule that rejected it
+    rule: String,
+    /// Position (1-based) and value of each conflicting word
+    words: Vec<(usize, String)>,
+}
+
+/// A set of rules that must all hold for a passphrase to be valid
+#[derive(Debug, PartialEq)]
+struct Policy {
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Builds a policy from a list of rule specs (e.g. "min-words 3").
+    /// Blank lines and lines starting with '#' are ignored, so the
+    /// contents of a policy file can be passed directly.
+    fn from_specs<'a, I>(specs: I) -> Result<Policy, String>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut rules = Vec::new();
+        for spec in specs.map(|s| s.trim()) {
+            if spec.is_empty() || spec.starts_with('#') {
+                continue;
+            }
+            let mut tokens = spec.split_whitespace();
+            let name = tokens.next().unwrap();
+            let rule = Rule::from_spec(name, tokens.next())?;
+            if let Some(extra) = tokens.next() {
+                return Err(format!("Unexpected '{}' after rule '{}'", extra, name));
+            }
+            rules.push(rule);
+        }
+        Ok(Policy { rules })
+    }
+
+    /// Checks a passphrase against every rule, returning the first rule
+    /// that rejects it.
+    fn check(&self, passphrase: &str) -> Result<(), &Rule> {
+        let words: Vec<&str> = passphrase.split_whitespace().collect();
+        match self.rules.iter().find(|rule| !rule.allows(&words)) {
+            Some(rule) => Err(rule),
+            None => Ok(()),
+        }
+    }
+
+    /// Like check(), but describes which words broke the rule
+    fn reject(&self, line: usize, passphrase: &str) -> Option<Rejection> {
+        let words: Vec<&str> = passphrase.split_whitespace().collect();
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                rule.conflicts(&words).map(|positions| Rejection {
+                    line,
+                    rule: rule.name(),
+                    words: positions
+                        .into_iter()
+                        .map(|i| (i + 1, String::from(words[i])))
+                        .collect(),
+                })
+            })
+            .next()
+    }
+}
+
+impl Default for Policy {
+    /// The part 1 rule, which is used when no rules are selected
+    fn default() -> Policy {
+        Policy {
+            rules: vec![Rule::NoDuplicates],
+        }
+    }
+}
+
+/// Returns a key that is identical for any two words that are anagrams
+/// of each other. Sorting by `char` rather than by byte keeps multi-byte
+/// characters intact.
+fn anagram_key(word: &str) -> Vec<char> {
+    let mut key: Vec<char> = word.chars().collect();
+    key.sort_unstable();
+    key
+}
+
+/// Levenshtein distance between two words (in characters). Only the
+/// previous row of the edit matrix is kept around.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Counts the valid passphrases in the input, one line at a time
+fn valid_passphrases<R: BufRead>(input: R, policy: &Policy) -> io::Result<usize> {
+    let mut valid = 0;
+    for line in input.lines() {
+        if policy.check(&line?).is_ok() {
+            valid += 1;
+        }
+    }
+    Ok(valid)
+}
+
+/// Lists every passphrase in the input that the policy rejects, along
+/// with the number of valid passphrases.
+fn rejections<R: BufRead>(input: R, policy: &Policy) -> io::Result<(Vec<Rejection>, usize)> {
+    let (mut rejections, mut valid) = (Vec::new(), 0);
+    for (i, line) in input.lines().enumerate() {
+        match policy.reject(i + 1, &line?) {
+            Some(rejection) => rejections.push(rejection),
+            None => valid += 1,
+        }
+    }
+    Ok((rejections, valid))
+}
+
+/// Where a list of passphrases is read from
+#[derive(Debug, PartialEq)]
+enum Source {
+    /// Passphrases given directly on the command line
+    Argv(String),
+    Stdin,
+    File(String),
 }
 
-fn valid_passphrases(input: String) -> usize {
-    input.lines().filter(|line| is_valid(line)).count()
+impl Source {
+    /// "-" is stdin, and anything else is a list of passphrases (i.e. the
+    /// "$(cat inputfile)" form). Files are given with --file instead.
+    fn from_arg(arg: &str) -> Source {
+        if arg == "-" {
+            Source::Stdin
+        } else {
+            Source::Argv(String::from(arg))
+        }
+    }
+
+    fn name(&self) -> &str {
+        match *self {
+            Source::Argv(_) => "<argv>",
+            Source::Stdin => "<stdin>",
+            Source::File(ref filename) => filename,
+        }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        Ok(match *self {
+            Source::Argv(ref input) => Box::new(io::Cursor::new(input.clone().into_bytes())),
+            Source::Stdin => Box::new(BufReader::new(io::stdin())),
+            Source::File(ref filename) => Box::new(BufReader::new(File::open(filename)?)),
+        })
+    }
+}
+
+/// How the rejection report is printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl ReportFormat {
+    fn from_str(s: &str) -> Option<ReportFormat> {
+        match s {
+            "text" => Some(ReportFormat::Text),
+            "json" => Some(ReportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Human-readable report: one line per rejected passphrase, followed by
+/// the number of valid passphrases.
+fn report_text(rejections: &[Rejection], valid: usize) -> String {
+    let mut report = String::new();
+    for rejection in rejections {
+        report += &format!("line {}: rejected by {}", rejection.line, rejection.rule);
+        let words: Vec<String> = rejection
+            .words
+            .iter()
+            .map(|&(position, ref word)| format!("word {} \"{}\"", position, word))
+            .collect();
+        if !words.is_empty() {
+            report += &format!(" ({})", words.join(", "));
+        }
+        report += "\n";
+    }
+    report + &format!("valid: {}\n", valid)
+}
+
+/// JSON report with the same contents as report_text()
+fn report_json(rejections: &[Rejection], valid: usize) -> String {
+    let entries: Vec<String> = rejections
+        .iter()
+        .map(|rejection| {
+            let words: Vec<String> = rejection
+                .words
+                .iter()
+                .map(|&(position, ref word)| {
+                    format!(
+                        "{{\"position\":{},\"word\":{}}}",
+                        position,
+                        json_string(word)
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"line\":{},\"rule\":{},\"words\":[{}]}}",
+                rejection.line,
+                json_string(&rejection.rule),
+                words.join(",")
+            )
+        })
+        .collect();
+    format!(
+        "{{\"valid\":{},\"invalid\":[{}]}}\n",
+        valid,
+        entries.join(",")
+    )
+}
+
+/// Quotes a string for JSON output
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if (c as u32) < 0x20 => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted + "\""
+}
+
+/// All words that are anagrams of each other across a whole corpus
+#[derive(Debug, Default)]
+struct AnagramClass {
+    /// Ids of the distinct words in the class, in order of appearance
+    words: Vec<usize>,
+    /// Total number of times any of the words appeared
+    occurrences: u64,
+    /// (input, line) pairs (both 0-based) that contain the class
+    lines: Vec<(u32, u32)>,
+}
+
+/// Groups every word of a corpus into anagram classes. Words are interned
+/// so each distinct word is only sorted into its anagram key once, which
+/// keeps large corpora (with few distinct words) cheap.
+#[derive(Debug, Default)]
+struct Analysis {
+    word_ids: HashMap<String, usize>,
+    /// Indexed by word id
+    words: Vec<String>,
+    counts: Vec<u64>,
+    word_class: Vec<usize>,
+    class_ids: HashMap<Vec<char>, usize>,
+    classes: Vec<AnagramClass>,
+}
+
+impl Analysis {
+    fn add_word(&mut self, word: &str, input: u32, line: u32) {
+        let id = match self.word_ids.get(word) {
+            Some(&id) => id,
+            None => {
+                let id = self.words.len();
+                let next_class = self.classes.len();
+                let class = *self
+                    .class_ids
+                    .entry(anagram_key(word))
+                    .or_insert(next_class);
+                if class == next_class {
+                    self.classes.push(AnagramClass::default());
+                }
+                self.classes[class].words.push(id);
+                self.word_ids.insert(String::from(word), id);
+                self.words.push(String::from(word));
+                self.counts.push(0);
+                self.word_class.push(class);
+                id
+            }
+        };
+
+        self.counts[id] += 1;
+        let class = &mut self.classes[self.word_class[id]];
+        class.occurrences += 1;
+        if class.lines.last() != Some(&(input, line)) {
+            class.lines.push((input, line));
+        }
+    }
+
+    /// Adds every word from one input, a line at a time
+    fn add_input<R: BufRead>(&mut self, input: u32, reader: R) -> io::Result<()> {
+        for (line, text) in reader.lines().enumerate() {
+            for word in text?.split_whitespace() {
+                self.add_word(word, input, line as u32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Classes containing more than one distinct word, largest first
+    fn collisions(&self) -> Vec<&AnagramClass> {
+        let mut classes: Vec<&AnagramClass> = self
+            .classes
+            .iter()
+            .filter(|class| class.words.len() > 1)
+            .collect();
+        classes.sort_by_key(|class| Reverse((class.words.len(), class.occurrences)));
+        classes
+    }
+
+    /// Words that appear more than once, most frequent first
+    fn duplicates(&self) -> Vec<(&str, u64)> {
+        let mut duplicates: Vec<(&str, u64)> = self
+            .words
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|&(_, &count)| count > 1)
+            .map(|(word, &count)| (word.as_str(), count))
+            .collect();
+        duplicates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        duplicates
+    }
+
+    /// Number of classes for each class size (in distinct words)
+    fn class_sizes(&self) -> Vec<(usize, usize)> {
+        let mut sizes = HashMap::new();
+        for class in &self.classes {
+            *sizes.entry(class.words.len()).or_insert(0) += 1;
+        }
+        let mut sizes: Vec<(usize, usize)> = sizes.into_iter().collect();
+        sizes.sort();
+        sizes
+    }
+
+    /// Human-readable summary, listing at most `top` classes and words, and
+    /// the first `top` lines of each class. Lines are prefixed with the
+    /// input name if there are several inputs.
+    fn report(&self, top: usize, names: &[&str]) -> String {
+        let location = |&(input, line): &(u32, u32)| {
+            if names.len() > 1 {
+                format!("{}:{}", names[input as usize], line + 1)
+            } else {
+                format!("{}", line + 1)
+            }
+        };
+
+        let mut report = format!(
+            "words: {} ({} distinct, {} anagram classes)\n",
+            self.counts.iter().sum::<u64>(),
+            self.words.len(),
+            self.classes.len()
+        );
+
+        report += "class sizes:\n";
+        for (size, classes) in self.class_sizes() {
+            report += &format!("  {} word(s): {} class(es)\n", size, classes);
+        }
+
+        let collisions = self.collisions();
+        report += &format!("anagram classes with collisions: {}\n", collisions.len());
+        for class in collisions.iter().take(top) {
+            let words: Vec<&str> = class
+                .words
+                .iter()
+                .map(|&id| self.words[id].as_str())
+                .collect();
+            let lines: Vec<String> = class.lines.iter().take(top).map(&location).collect();
+            let more = match class.lines.len().saturating_sub(top) {
+                0 => String::new(),
+                more => format!(" (and {} more)", more),
+            };
+            report += &format!(
+                "  {} ({} occurrences) on lines {}{}\n",
+                words.join(" "),
+                class.occurrences,
+                lines.join(", "),
+                more
+            );
+        }
+
+        let duplicates = self.duplicates();
+        report += &format!("duplicate words: {}\n", duplicates.len());
+        for &(word, count) in duplicates.iter().take(top) {
+            report += &format!("  {} {}\n", word, count);
+        }
+        report
+    }
+}
+
+/// Generates passphrases from a wordlist that satisfy the no-duplicates
+/// or no-anagrams rule
+struct Generator {
+    /// Words grouped into classes that can't share a passphrase: one class
+    /// per distinct word for no-duplicates, or per anagram for no-anagrams
+    classes: Vec<Vec<String>>,
+}
+
+impl Generator {
+    fn new<'a, I>(wordlist: I, rule: Rule) -> Result<Generator, String>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut seen = HashSet::new();
+        let words = wordlist.filter(|word| seen.insert(*word));
+
+        let classes = match rule {
+            Rule::NoDuplicates => words.map(|word| vec![String::from(word)]).collect(),
+            Rule::NoAnagrams => {
+                // Keep the classes in wordlist order so a seed always gives
+                // the same passphrases for the same wordlist
+                let mut index = HashMap::new();
+                let mut classes: Vec<Vec<String>> = Vec::new();
+                for word in words {
+                    let class = *index.entry(anagram_key(word)).or_insert(classes.len());
+                    if class == classes.len() {
+                        classes.push(vec![]);
+                    }
+                    classes[class].push(String::from(word));
+                }
+                classes
+            }
+            _ => return Err(format!("Can't generate passphrases for '{}'", rule.name())),
+        };
+        Ok(Generator { classes })
+    }
+
+    /// Picks `words` words from distinct classes (uniformly, without
+    /// replacement), then a uniform word from each class. Returns the
+    /// passphrase and its entropy in bits, i.e. -log2 of the chance of
+    /// generating that exact passphrase.
+    fn generate<R: Rng>(&self, rng: &mut R, words: usize) -> Result<(String, f64), String> {
+        let available = self.classes.len();
+        if words > available {
+            return Err(format!(
+                "Wordlist only has {} usable words, {} requested",
+                available, words
+            ));
+        }
+
+        // Rejection sampling is fine here since passphrases are short
+        // compared to any useful wordlist.
+        let mut used = HashSet::new();
+        let mut passphrase = Vec::with_capacity(words);
+        let mut bits = 0.0;
+        while passphrase.len() < words {
+            let i = rng.gen_range(0, available);
+            if used.insert(i) {
+                let class = &self.classes[i];
+                bits += ((available - passphrase.len()) as f64).log2();
+                bits += (class.len() as f64).log2();
+                passphrase.push(rng.choose(class).unwrap().as_str());
+            }
+        }
+        Ok((passphrase.join(" "), bits))
+    }
 }
 
 fn print_usage() {
     println!("Day 4: High Entropy Passphrase");
     println!("Usage:");
-    println!("04 <input>");
-    println!("  <inputs> - One or more passphrases (newline separated)");
+    println!("04 [--report <format>] [--policy <file>] [<rules>] <input>...");
+    println!("  --report <format> - List invalid passphrases (text / json)");
+    println!("  --policy <file>   - Read rules from a file (one per line)");
+    println!("  <rules>           - Any of the following (default: --no-duplicates)");
+    println!("     --no-duplicates        No repeated words (part 1)");
+    println!("     --no-anagrams          No words that are anagrams (part 2)");
+    println!("     --no-case-duplicates   No repeated words, ignoring case");
+    println!("     --min-words <n>        At least n words");
+    println!("     --min-length <n>       Every word at least n characters");
+    println!("     --edit-distance <n>    No two words within n edits");
+    println!("  <input>           - One or more passphrases (newline separated), '-'");
+    println!("                      for stdin, or --file <path> for a passphrase");
+    println!("                      file. Counts are given per input (and in total)");
+    println!("                      for several.");
+    println!("04 generate [--no-anagrams] [--count <n>] [--seed <n>] <wordlist> <words>");
+    println!("  --no-anagrams     - Generate passphrases without anagrams (part 2)");
+    println!("  --count <n>       - Number of passphrases to generate (default: 1)");
+    println!("  --seed <n>        - Seed for reproducible passphrases");
+    println!("  <wordlist>        - File of candidate words (whitespace separated)");
+    println!("  <words>           - Number of words per passphrase");
+    println!("  Each passphrase is printed with its estimated entropy in bits.");
+    println!("04 analyze [--top <n>] <input>...");
+    println!("  --top <n>         - Number of classes, words and lines per class to");
+    println!("                      list (default: 10)");
+    println!("  <input>           - As above. Groups every word into anagram classes");
+    println!("                      and lists collisions and duplicates across lines.");
 }
 
-fn main() {
-    if let (2, Some(input)) = (args().count(), args().nth(1)) {
-        println!("{}", valid_passphrases(input));
-    } else {
+struct Options {
+    policy: Policy,
+    report: Option<ReportFormat>,
+    inputs: Vec<Source>,
+}
+
+/// Reads a policy file into a list of rules
+fn rules_from_file(filename: &str) -> Result<Vec<Rule>, String> {
+    let mut contents = String::new();
+    File::open(filename)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("IO Error: {}", e))?;
+    Policy::from_specs(contents.lines()).map(|policy| policy.rules)
+}
+
+/// Parses the command line. Err(None) means the usage should be printed.
+fn parse_args(args: &[String]) -> Result<Options, Option<String>> {
+    let mut rules = Vec::new();
+    let mut report = None;
+    let mut inputs = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--report" {
+            let format = args.next().ok_or(None)?;
+            report = Some(
+                ReportFormat::from_str(format)
+                    .ok_or_else(|| format!("Unknown report format '{}'", format))?,
+            );
+        } else if arg == "--policy" {
+            let filename = args.next().ok_or(None)?;
+            rules.extend(rules_from_file(filename)?);
+        } else if arg == "--file" {
+            inputs.push(Source::File(args.next().ok_or(None)?.clone()));
+        } else if let Some(name) = arg.strip_prefix("--") {
+            let value = if Rule::takes_arg(name) {
+                Some(args.next().ok_or(None)?.as_str())
+            } else {
+                None
+            };
+            rules.push(Rule::from_spec(name, value)?);
+        } else {
+            inputs.push(Source::from_arg(arg));
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(None);
+    }
+
+    Ok(Options {
+        policy: if rules.is_empty() {
+            Policy::default()
+        } else {
+            Policy { rules }
+        },
+        report,
+        inputs,
+    })
+}
+
+/// Options for generating passphrases
+struct GenerateOptions {
+    rule: Rule,
+    count: usize,
+    seed: Option<usize>,
+    wordlist: String,
+    words: usize,
+}
+
+/// Parses the command line after "generate". Err(None) means the usage
+/// should be printed.
+fn parse_generate_args(args: &[String]) -> Result<GenerateOptions, Option<String>> {
+    let number = |arg: Option<&String>| -> Result<usize, Option<String>> {
+        let arg = arg.ok_or(None)?;
+        arg.parse::<usize>()
+            .map_err(|_| Some(format!("Expected a number, got '{}'", arg)))
+    };
+
+    let (mut rule, mut count, mut seed) = (Rule::NoDuplicates, 1, None);
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-anagrams" => rule = Rule::NoAnagrams,
+            "--count" => count = number(args.next())?,
+            "--seed" => seed = Some(number(args.next())?),
+            _ => positional.push(arg),
+        }
+    }
+
+    match positional.as_slice() {
+        [wordlist, words] => Ok(GenerateOptions {
+            rule,
+            count,
+            seed,
+            wordlist: (*wordlist).clone(),
+            words: number(Some(words))?,
+        }),
+        _ => Err(None),
+    }
+}
+
+fn generate_main(args: &[String]) {
+    let options = match parse_generate_args(args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let mut wordlist = String::new();
+    if let Err(e) =
+        File::open(&options.wordlist).and_then(|mut file| file.read_to_string(&mut wordlist))
+    {
+        println!("IO Error ({}): {}", options.wordlist, e);
+        exit(-1);
+    }
+
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::from_seed(&[seed][..]),
+        None => StdRng::new().expect("Couldn't seed the random number generator"),
+    };
+    let generated = Generator::new(wordlist.split_whitespace(), options.rule).and_then(|gen| {
+        (0..options.count)
+            .map(|_| gen.generate(&mut rng, options.words))
+            .collect::<Result<Vec<_>, _>>()
+    });
+
+    match generated {
+        Ok(passphrases) => {
+            for (passphrase, bits) in passphrases {
+                println!("{}\t{:.1} bits", passphrase, bits);
+            }
+        }
+        Err(message) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+    }
+}
+
+fn analyze_main(args: &[String]) {
+    let mut top = 10;
+    let mut inputs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--top" {
+            match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => top = n,
+                _ => {
+                    print_usage();
+                    exit(-1);
+                }
+            }
+        } else if arg == "--file" {
+            match args.next() {
+                Some(filename) => inputs.push(Source::File(filename.clone())),
+                None => {
+                    print_usage();
+                    exit(-1);
+                }
+            }
+        } else {
+            inputs.push(Source::from_arg(arg));
+        }
+    }
+    if inputs.is_empty() {
         print_usage();
         exit(-1);
     }
+
+    let mut analysis = Analysis::default();
+    for (i, source) in inputs.iter().enumerate() {
+        if let Err(e) = source
+            .open()
+            .and_then(|reader| analysis.add_input(i as u32, reader))
+        {
+            println!("IO Error ({}): {}", source.name(), e);
+            exit(-1);
+        }
+    }
+
+    let names: Vec<&str> = inputs.iter().map(|source| source.name()).collect();
+    print!("{}", analysis.report(top, &names));
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        Some("generate") => return generate_main(&args[1..]),
+        Some("analyze") => return analyze_main(&args[1..]),
+        _ => {}
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let several = options.inputs.len() > 1;
+    let mut total = 0;
+    let mut reports = Vec::new();
+
+    for source in &options.inputs {
+        let result = source.open().and_then(|input| match options.report {
+            Some(_) => rejections(input, &options.policy),
+            None => valid_passphrases(input, &options.policy).map(|valid| (vec![], valid)),
+        });
+        let (rejections, valid) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("IO Error ({}): {}", source.name(), e);
+                exit(-1);
+            }
+        };
+        total += valid;
+
+        match options.report {
+            Some(ReportFormat::Text) if several => {
+                print!("{}:\n{}", source.name(), report_text(&rejections, valid))
+            }
+            Some(ReportFormat::Text) => print!("{}", report_text(&rejections, valid)),
+            Some(ReportFormat::Json) if several => reports.push(format!(
+                "{{\"name\":{},\"report\":{}}}",
+                json_string(source.name()),
+                report_json(&rejections, valid).trim_end()
+            )),
+            Some(ReportFormat::Json) => print!("{}", report_json(&rejections, valid)),
+            None if several => println!("{}: {}", source.name(), valid),
+            None => println!("{}", valid),
+        }
+    }
+
+    match options.report {
+        _ if !several => {}
+        Some(ReportFormat::Json) => {
+            println!("{{\"total\":{},\"inputs\":[{}]}}", total, reports.join(","))
+        }
+        _ => println!("total: {}", total),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::env;
+    use std::fs;
+
+    fn policy(rules: &[Rule]) -> Policy {
+        Policy {
+            rules: rules.to_vec(),
+        }
+    }
 
     #[test]
     fn valid_passphrase() {
-        assert!(is_valid(" abc de f g abcd"));
+        assert!(Rule::NoDuplicates.allows(&["abc", "de", "f", "g", "abcd"]));
     }
 
     #[test]
     fn invalid_passphrase() {
-        assert!(!is_valid(" abc de f   g    abc"));
+        assert!(Policy::default().check(" abc de f   g    abc").is_err());
+    }
+
+    #[test]
+    fn aoc_example_anagrams() {
+        let policy = policy(&[Rule::NoAnagrams]);
+        assert!(policy.check("abcde fghij").is_ok());
+        assert!(policy.check("abcde xyz ecdab").is_err());
+        assert!(policy.check("a ab abc abd abf abj").is_ok());
+        assert!(policy.check("iiii oiii ooii oooi oooo").is_ok());
+        assert!(policy.check("oiii ioii iioi iiio").is_err());
+    }
+
+    #[test]
+    fn unicode_anagrams() {
+        assert!(!Rule::NoAnagrams.allows(&["étè", "tèé"]));
+        assert!(Rule::NoAnagrams.allows(&["été", "ete"]));
+    }
+
+    #[test]
+    fn house_rules() {
+        assert!(!Rule::NoCaseDuplicates.allows(&["Ab", "cd", "aB"]));
+        assert!(!Rule::MinWords(3).allows(&["ab", "cd"]));
+        assert!(!Rule::MinLength(3).allows(&["abc", "de"]));
+        assert!(!Rule::EditDistance(1).allows(&["kitten", "sitten"]));
+        assert!(Rule::EditDistance(2).allows(&["kitten", "sitting"]));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("flaw", "lawn"), 2);
+        assert_eq!(edit_distance("étè", "ete"), 2);
+    }
+
+    #[test]
+    fn rejecting_rule_name() {
+        let policy = policy(&[Rule::MinWords(2), Rule::NoAnagrams]);
+        assert_eq!(policy.check("ab ba").unwrap_err().name(), "no-anagrams");
+        assert_eq!(policy.check("ab").unwrap_err().name(), "min-words 2");
+    }
+
+    #[test]
+    fn conflicting_words() {
+        assert_eq!(
+            Rule::NoDuplicates.conflicts(&["a", "b", "c", "b"]),
+            Some(vec![1, 3])
+        );
+        assert_eq!(
+            Rule::NoAnagrams.conflicts(&["ab", "cd", "ba"]),
+            Some(vec![0, 2])
+        );
+        assert_eq!(
+            Rule::MinLength(2).conflicts(&["a", "bc", "d"]),
+            Some(vec![0, 2])
+        );
+        assert_eq!(Rule::MinWords(2).conflicts(&["a"]), Some(vec![]));
+        assert_eq!(Rule::NoDuplicates.conflicts(&["a", "b"]), None);
+    }
+
+    #[test]
+    fn rejection_report() {
+        let input = "ab cd\nab ba\nx\nab\"c c\"ab";
+        let policy = policy(&[Rule::MinWords(2), Rule::NoAnagrams]);
+        let (rejections, valid) = rejections(input.as_bytes(), &policy).unwrap();
+        assert_eq!(valid, 1);
+        assert_eq!(
+            rejections,
+            vec![
+                Rejection {
+                    line: 2,
+                    rule: String::from("no-anagrams"),
+                    words: vec![(1, String::from("ab")), (2, String::from("ba"))],
+                },
+                Rejection {
+                    line: 3,
+                    rule: String::from("min-words 2"),
+                    words: vec![],
+                },
+                Rejection {
+                    line: 4,
+                    rule: String::from("no-anagrams"),
+                    words: vec![(1, String::from("ab\"c")), (2, String::from("c\"ab"))],
+                },
+            ]
+        );
+        assert_eq!(
+            report_text(&rejections[..2], 1),
+            "line 2: rejected by no-anagrams (word 1 \"ab\", word 2 \"ba\")\n\
+             line 3: rejected by min-words 2\n\
+             valid: 1\n"
+        );
+        assert_eq!(
+            report_json(&rejections[1..], 1),
+            "{\"valid\":1,\"invalid\":[\
+             {\"line\":3,\"rule\":\"min-words 2\",\"words\":[]},\
+             {\"line\":4,\"rule\":\"no-anagrams\",\"words\":[\
+             {\"position\":1,\"word\":\"ab\\\"c\"},{\"position\":2,\"word\":\"c\\\"ab\"}]}]}\n"
+        );
+    }
+
+    #[test]
+    fn policy_from_specs() {
+        let file = "# house rules\nno-anagrams\n\nmin-length 4\n";
+        assert_eq!(
+            Policy::from_specs(file.lines()),
+            Ok(policy(&[Rule::NoAnagrams, Rule::MinLength(4)]))
+        );
+        assert!(Policy::from_specs("min-words".lines()).is_err());
+        assert!(Policy::from_specs("no-anagrams 3".lines()).is_err());
+        assert!(Policy::from_specs("no-vowels".lines()).is_err());
     }
 
     #[test]
     fn multiple_valid() {
-        let input = String::from("ab cd \n ab ab \n ab cd \n ab cd");
-        assert_eq!(valid_passphrases(input), 3);
+        let input = "ab cd \n ab ab \n ab cd \n ab cd".as_bytes();
+        assert_eq!(valid_passphrases(input, &Policy::default()).unwrap(), 3);
     }
 
     #[test]
     fn no_valid() {
-        let input = String::from("ab ab\nab ab\nab ab\nab ab");
-        assert_eq!(valid_passphrases(input), 0);
+        let input = "ab ab\nab ab\nab ab\nab ab".as_bytes();
+        assert_eq!(valid_passphrases(input, &Policy::default()).unwrap(), 0);
+    }
+
+    #[test]
+    fn multiple_valid_anagrams() {
+        let input = "ab cd\nab ba\nab ab\nabc bca d".as_bytes();
+        let policy = policy(&[Rule::NoAnagrams]);
+        assert_eq!(valid_passphrases(input, &policy).unwrap(), 1);
+    }
+
+    #[test]
+    fn generated_passphrases_are_valid() {
+        let wordlist = "ab ba abc cab bca de ed f gh hg ij";
+        let mut rng = StdRng::from_seed(&[4][..]);
+        for &rule in &[Rule::NoDuplicates, Rule::NoAnagrams] {
+            let generator = Generator::new(wordlist.split_whitespace(), rule).unwrap();
+            for _ in 0..100 {
+                let (passphrase, _) = generator.generate(&mut rng, 5).unwrap();
+                assert!(policy(&[rule, Rule::MinWords(5)])
+                    .check(&passphrase)
+                    .is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn generator_entropy() {
+        let mut rng = StdRng::from_seed(&[4][..]);
+
+        // 4 * 3 * 2 equally likely passphrases
+        let generator = Generator::new("a b c d a".split_whitespace(), Rule::NoDuplicates);
+        let (_, bits) = generator.unwrap().generate(&mut rng, 3).unwrap();
+        assert!((bits - 24f64.log2()).abs() < 1e-9);
+
+        // Two classes of two words: 2 * 2 orders * 2 * 2 words
+        let generator = Generator::new("ab ba cd dc".split_whitespace(), Rule::NoAnagrams);
+        let generator = generator.unwrap();
+        let (_, bits) = generator.generate(&mut rng, 2).unwrap();
+        assert!((bits - 3.0).abs() < 1e-9);
+        assert!(generator.generate(&mut rng, 3).is_err());
+    }
+
+    #[test]
+    fn seeded_generator_is_reproducible() {
+        let words = "a b c d e f g h i j k l m n o p";
+        let generator = Generator::new(words.split_whitespace(), Rule::NoDuplicates).unwrap();
+        let run = |seed| {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            (0..10)
+                .map(|_| generator.generate(&mut rng, 4).unwrap().0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn anagram_classes() {
+        let mut analysis = Analysis::default();
+        let corpus = "ab cd ba\nxy ab\nba dc dc\nyx";
+        analysis.add_input(0, corpus.as_bytes()).unwrap();
+        analysis.add_input(1, "ef".as_bytes()).unwrap();
+
+        assert_eq!(analysis.words.len(), 7);
+        assert_eq!(analysis.classes.len(), 4);
+        assert_eq!(analysis.class_sizes(), vec![(1, 1), (2, 3)]);
+        assert_eq!(analysis.duplicates(), vec![("ab", 2), ("ba", 2), ("dc", 2)]);
+
+        let collisions = analysis.collisions();
+        assert_eq!(collisions.len(), 3);
+        assert_eq!(collisions[0].occurrences, 4);
+        assert_eq!(collisions[0].lines, vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(collisions[2].lines, vec![(0, 1), (0, 3)]);
+
+        let report = analysis.report(1, &["a", "b"]);
+        assert!(report.starts_with("words: 10 (7 distinct, 4 anagram classes)\n"));
+        assert!(report.contains("  ab ba (4 occurrences) on lines a:1 (and 2 more)\n"));
+        assert!(report.contains("duplicate words: 3\n  ab 2\n"));
+        let report = analysis.report(3, &["a", "b"]);
+        assert!(report.contains("  ab ba (4 occurrences) on lines a:1, a:2, a:3\n"));
+    }
+
+    fn inputs(args: &[&str]) -> Vec<Source> {
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        parse_args(&args).ok().unwrap().inputs
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!(Source::from_arg("-"), Source::Stdin);
+        assert_eq!(
+            Source::from_arg("ab cd\nef"),
+            Source::Argv(String::from("ab cd\nef"))
+        );
+
+        // A single passphrase is still a passphrase, not a filename
+        assert_eq!(inputs(&["abc"]), vec![Source::Argv(String::from("abc"))]);
+        let source = Source::from_arg("abc");
+        assert_eq!(
+            valid_passphrases(source.open().unwrap(), &Policy::default()).unwrap(),
+            1
+        );
+
+        // Files are only read with --file, even if the path has a space
+        assert_eq!(
+            inputs(&["--file", "my list.txt", "--file", "b.txt", "-"]),
+            vec![
+                Source::File(String::from("my list.txt")),
+                Source::File(String::from("b.txt")),
+                Source::Stdin,
+            ]
+        );
+        let path = env::temp_dir().join("advent-2017-04 passphrases.txt");
+        fs::write(&path, "aa bb aa\n").unwrap();
+        let source = Source::File(path.to_string_lossy().into_owned());
+        assert_eq!(
+            valid_passphrases(source.open().unwrap(), &Policy::default()).unwrap(),
+            0
+        );
+        fs::remove_file(&path).unwrap();
+
+        let mut lines = String::new();
+        let source = Source::from_arg("ab cd\nef");
+        source.open().unwrap().read_to_string(&mut lines).unwrap();
+        assert_eq!(lines, "ab cd\nef");
     }
 }
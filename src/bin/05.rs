@@ -1,40 +1,792 @@
+#![feature(test)]
+
+extern crate advent_2017;
+extern crate rand;
+extern crate test;
+
+use advent_2017::cycle::{self, Method};
+use advent_2017::hash::splitmix64;
+use rand::{Rng, SeedableRng, StdRng};
 use std::env::args;
-use std::num;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::prelude::*;
+use std::io::BufWriter;
+use std::num;
 use std::process::exit;
 
+/// An affine function of an offset, i.e. `mul * x + add`. Saturates at
+/// the bounds of i32 rather than overflowing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Affine {
+    mul: i32,
+    add: i32,
+}
+
+impl Affine {
+    /// Parses expressions like "x+1", "-x", "2*x-3" or "5"
+    fn from_str(s: &str) -> Option<Affine> {
+        let number = |s: &str| s.parse::<i32>().ok();
+
+        let (coefficient, constant) = match s.find('x') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => return number(s).map(|add| Affine { mul: 0, add }),
+        };
+
+        let mul = match coefficient.trim_end_matches('*') {
+            "" | "+" => Some(1),
+            "-" => Some(-1),
+            n => number(n),
+        };
+        let add = match constant {
+            "" => Some(0),
+            c if c.starts_with('+') || c.starts_with('-') => number(c),
+            _ => None,
+        };
+
+        match (mul, add) {
+            (Some(mul), Some(add)) => Some(Affine { mul, add }),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, x: i32) -> i32 {
+        x.saturating_mul(self.mul).saturating_add(self.add)
+    }
+}
+
+/// Comparison used by threshold rules
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    EQ, // ==
+    NE, // !=
+    LT, // <
+    GT, // >
+    GE, // >=
+    LE, // <=
+}
+
+impl Comparison {
+    /// Splits a leading comparison operator from the rest of the string
+    fn split(s: &str) -> Option<(Comparison, &str)> {
+        // Two-character operators have to be tried first
+        let operators = [
+            ("==", Comparison::EQ),
+            ("!=", Comparison::NE),
+            (">=", Comparison::GE),
+            ("<=", Comparison::LE),
+            ("<", Comparison::LT),
+            (">", Comparison::GT),
+        ];
+        operators
+            .iter()
+            .find(|&&(op, _)| s.starts_with(op))
+            .map(|&(op, comparison)| (comparison, &s[op.len()..]))
+    }
+
+    fn eval(&self, a: i32, b: i32) -> bool {
+        match *self {
+            Comparison::EQ => a == b,
+            Comparison::NE => a != b,
+            Comparison::LT => a < b,
+            Comparison::GT => a > b,
+            Comparison::GE => a >= b,
+            Comparison::LE => a <= b,
+        }
+    }
+}
+
+/// How an offset changes after it's been jumped from
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rule {
+    /// The same affine function for every offset (e.g. x+1 for part 1)
+    Affine(Affine),
+    /// One function for offsets matching `x <comparison> value`, and
+    /// another for the rest (e.g. x>=3?x-1:x+1 for part 2)
+    Threshold {
+        comparison: Comparison,
+        value: i32,
+        then: Affine,
+        otherwise: Affine,
+    },
+    /// Increment, but never past `max` (i.e. a saturating counter)
+    Saturating { max: i32 },
+    /// Increment, wrapping around from `modulus - 1` to `-modulus`
+    Wrapping { modulus: i32 },
+}
+
+impl Rule {
+    /// Always increment the offset (part 1)
+    const INCREMENT: Rule = Rule::Affine(Affine { mul: 1, add: 1 });
+
+    /// Decrement offsets of three or more, increment the rest (part 2)
+    const STRANGE: Rule = Rule::Threshold {
+        comparison: Comparison::GE,
+        value: 3,
+        then: Affine { mul: 1, add: -1 },
+        otherwise: Affine { mul: 1, add: 1 },
+    };
+
+    /// Parses a rule, either by name:
+    ///   increment, strange, negate, saturate:<max>, wrap:<modulus>
+    /// or as an expression in x (the offset), either affine or threshold:
+    ///   x+1, -x, 2*x-1, x>=3?x-1:x+1
+    fn from_str(s: &str) -> Result<Rule, String> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let invalid = || format!("Invalid rule '{}'", s);
+        let parameter = |name: &str| {
+            s[name.len()..]
+                .parse::<i32>()
+                .map_err(|_| format!("Expected {}<number>, got '{}'", name, s))
+        };
+
+        match s.as_str() {
+            "increment" => return Ok(Rule::INCREMENT),
+            "strange" => return Ok(Rule::STRANGE),
+            "negate" => return Ok(Rule::Affine(Affine { mul: -1, add: 0 })),
+            _ if s.starts_with("saturate:") => {
+                return parameter("saturate:").map(|max| Rule::Saturating { max })
+            }
+            _ if s.starts_with("wrap:") => {
+                return match parameter("wrap:")? {
+                    modulus if modulus > 0 => Ok(Rule::Wrapping { modulus }),
+                    _ => Err(format!("Modulus must be positive in '{}'", s)),
+                }
+            }
+            _ => {}
+        }
+
+        // x<comparison><value>?<then>:<otherwise>
+        if let Some(question) = s.find('?') {
+            let (condition, branches) = (&s[..question], &s[question + 1..]);
+            let colon = branches.find(':').ok_or_else(invalid)?;
+            let (comparison, value) =
+                Comparison::split(condition.strip_prefix('x').ok_or_else(invalid)?)
+                    .ok_or_else(invalid)?;
+
+            return match (
+                value.parse::<i32>(),
+                Affine::from_str(&branches[..colon]),
+                Affine::from_str(&branches[colon + 1..]),
+            ) {
+                (Ok(value), Some(then), Some(otherwise)) => Ok(Rule::Threshold {
+                    comparison,
+                    value,
+                    then,
+                    otherwise,
+                }),
+                _ => Err(invalid()),
+            };
+        }
+
+        Affine::from_str(&s).map(Rule::Affine).ok_or_else(invalid)
+    }
+
+    fn apply(&self, offset: i32) -> i32 {
+        match *self {
+            Rule::Affine(f) => f.apply(offset),
+            Rule::Threshold {
+                comparison,
+                value,
+                then,
+                otherwise,
+            } => {
+                if comparison.eval(offset, value) {
+                    then.apply(offset)
+                } else {
+                    otherwise.apply(offset)
+                }
+            }
+            Rule::Saturating { max } => offset.saturating_add(1).min(max.max(offset)),
+            Rule::Wrapping { modulus } => {
+                let range = 2 * i64::from(modulus);
+                ((i64::from(offset) + 1 + i64::from(modulus)).rem_euclid(range)
+                    - i64::from(modulus)) as i32
+            }
+        }
+    }
+}
 
 /// Performs a single jump instruction. Returns Some() with the next
 /// index if the new index is inside the list of jumps, or None if
 /// it's out-of-bounds.
-fn take_jump(jumps: &mut Vec<i32>, index: usize) -> Option<usize> {
-    let new_index = (index as i32) + jumps[index];
+fn take_jump(jumps: &mut [i32], index: usize, rule: Rule) -> Option<usize> {
+    // Index arithmetic is done in usize, so lists longer than i32::MAX
+    // entries work too
+    match index.checked_add_signed(jumps[index] as isize) {
+        Some(new_index) if new_index < jumps.len() => {
+            jumps[index] = rule.apply(jumps[index]);
+            Some(new_index)
+        }
+        _ => None,
+    }
+}
+
+/// A single jump, as seen by a trace. The offset of the final jump (out
+/// of the list) isn't changed, so its old and new offsets are the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Step {
+    number: u64,
+    index: usize,
+    old: i32,
+    new: i32,
+}
+
+/// How a run through the jump list ended
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// Jumped out of the list after this many jumps
+    Escaped(u64),
+    /// Still inside the list (at `index`) after the maximum number of jumps
+    Stopped { steps: u64, index: usize },
+    /// Never escapes: the state after `start` jumps repeats every `length`
+    /// jumps
+    Loops { start: u64, length: u64 },
+    /// Still inside the list (at `index`) after u64::MAX jumps
+    Overflow { index: usize },
+}
+
+/// Jumps through the list from `start` until it's escaped or `max_steps`
+/// jumps have been taken, passing every jump to `trace`.
+fn run<F>(
+    jumps: &mut [i32],
+    start: usize,
+    rule: Rule,
+    max_steps: Option<u64>,
+    mut trace: F,
+) -> Outcome
+where
+    F: FnMut(Step),
+{
+    let (mut count, mut index): (u64, usize) = (0, start);
+    if index >= jumps.len() {
+        return Outcome::Escaped(0);
+    }
+
+    loop {
+        if Some(count) == max_steps {
+            return Outcome::Stopped {
+                steps: count,
+                index,
+            };
+        }
+
+        let old = jumps[index];
+        count = match count.checked_add(1) {
+            Some(count) => count,
+            None => return Outcome::Overflow { index },
+        };
+        let next_index = take_jump(jumps, index, rule);
+        trace(Step {
+            number: count,
+            index,
+            old,
+            new: jumps[index],
+        });
+
+        match next_index {
+            Some(next_index) => index = next_index,
+            None => return Outcome::Escaped(count),
+        }
+    }
+}
+
+/// Counts the number of jumps needed to break from the list, starting at
+/// `start`. Returns None if the count doesn't fit in a u64.
+fn count_jumps(mut jumps: Vec<i32>, start: usize, rule: Rule) -> Option<u64> {
+    match run(&mut jumps, start, rule, None, |_| {}) {
+        Outcome::Escaped(count) => Some(count),
+        Outcome::Overflow { .. } => None,
+        Outcome::Stopped { .. } | Outcome::Loops { .. } => unreachable!(),
+    }
+}
+
+/// Same result as count_jumps(), but faster on long runs. Common rules are
+/// matched once up front so the hot loop is specialized (and inlined) for
+/// them instead of dispatching on the rule for every jump.
+fn count_jumps_fast(mut jumps: Vec<i32>, start: usize, rule: Rule) -> Option<u64> {
+    match rule {
+        Rule::Affine(Affine { mul: 1, add }) => {
+            run_fast(&mut jumps, start, |x| x.saturating_add(add))
+        }
+        Rule::Threshold {
+            comparison: Comparison::GE,
+            value,
+            then: Affine { mul: 1, add: then },
+            otherwise:
+                Affine {
+                    mul: 1,
+                    add: otherwise,
+                },
+        } => run_fast(&mut jumps, start, |x| {
+            x.saturating_add(if x >= value { then } else { otherwise })
+        }),
+        rule => run_fast(&mut jumps, start, |x| rule.apply(x)),
+    }
+}
+
+/// The loop behind count_jumps_fast(). The new index is computed with
+/// wrapping arithmetic, so both ends of the list are checked with a single
+/// unsigned comparison (a jump off the front wraps to a huge index).
+#[inline(always)]
+fn run_fast<F>(jumps: &mut [i32], start: usize, update: F) -> Option<u64>
+where
+    F: Fn(i32) -> i32,
+{
+    let (mut count, mut index): (u64, usize) = (0, start);
+    while let Some(offset) = jumps.get_mut(index) {
+        count = count.checked_add(1)?;
+        index = index.wrapping_add(*offset as isize as usize);
+        *offset = update(*offset);
+    }
+    Some(count)
+}
+
+/// Random jump list whose shape depends on `reach`. Larger reaches
+/// usually take longer to escape:
+///   - From -1 to 0, offset i is uniform in [0, 2 + -reach * (len - i)],
+///     so jumps go forwards and can skip most of the list.
+///   - From 0 to 1, offset i is uniform in [-(reach * i), 2] (AoC-style),
+///     so most jumps go backwards.
+///   - Above 1, offset i is -i (straight back to the start) with
+///     probability 1 - 1 / reach, and uniform in [-i, 2] otherwise.
+fn random_jumps<R: Rng>(rng: &mut R, len: usize, reach: f64) -> Vec<i32> {
+    let clamp = |x: f64| x.min(f64::from(i32::MAX - 3)) as i32;
+    (0..len)
+        .map(|i| {
+            if reach < 0.0 {
+                rng.gen_range(0, clamp(-reach * (len - i) as f64) + 3)
+            } else if reach > 1.0 && rng.gen::<f64>() < 1.0 - 1.0 / reach {
+                -clamp(i as f64)
+            } else {
+                rng.gen_range(-clamp(reach.min(1.0) * i as f64), 3)
+            }
+        })
+        .collect()
+}
+
+/// How far a generated list's step count is from the target (u64::MAX if
+/// it didn't escape)
+fn step_error(steps: Option<u64>, target: u64) -> u64 {
+    match steps {
+        Some(steps) => steps.abs_diff(target),
+        None => u64::MAX,
+    }
+}
+
+/// Searches for a jump list of `len` jumps that escapes after roughly
+/// `target` steps under `rule`. The reach of random_jumps() is binary
+/// searched (until within 1% of the target), regenerating from the same
+/// seed each time, and the closest list found is returned along with its
+/// step count. Runs are capped at twice the target, so rules that never
+/// escape can't hang the search.
+fn generate_jumps(len: usize, target: u64, rule: Rule, seed: usize) -> (Vec<i32>, Option<u64>) {
+    const ITERATIONS: usize = 40;
+    const MAX_REACH: f64 = 1e9;
 
-    if new_index >= 0 && (new_index as usize) < jumps.len() {
-        jumps[index] += 1;
-        Some(new_index as usize)
+    let cap = target.saturating_mul(2).max(1);
+    let attempt = |reach: f64| {
+        let mut rng = StdRng::from_seed(&[seed][..]);
+        let jumps = random_jumps(&mut rng, len, reach);
+        let steps = match run(&mut jumps.clone(), 0, rule, Some(cap), |_| {}) {
+            Outcome::Escaped(steps) => Some(steps),
+            _ => None,
+        };
+        (jumps, steps)
+    };
+    let distance = |steps: Option<u64>| step_error(steps, target);
+    // Lists that don't escape within the cap count as too long
+    let too_short = |steps: Option<u64>| steps.is_some_and(|steps| steps < target);
+
+    let (mut low, mut high) = (0.0, 1.0);
+    let mut best = attempt(low);
+    if !too_short(best.1) {
+        // Even the shortest backwards jumps take too long, so search the
+        // forward jumps
+        low = -1.0;
+        high = 0.0;
     } else {
-        None
+        // Grow the range until it brackets the target
+        while high < MAX_REACH {
+            let (jumps, steps) = attempt(high);
+            let bracketed = !too_short(steps);
+            if distance(steps) < distance(best.1) {
+                best = (jumps, steps);
+            }
+            if bracketed {
+                break;
+            }
+            low = high;
+            high *= 2.0;
+        }
+    }
+
+    for _ in 0..ITERATIONS {
+        if distance(best.1) <= target / 100 {
+            break;
+        }
+        let reach = (low + high) / 2.0;
+        let (jumps, steps) = attempt(reach);
+        if too_short(steps) {
+            low = reach;
+        } else {
+            high = reach;
+        }
+        if distance(steps) < distance(best.1) {
+            best = (jumps, steps);
+        }
+    }
+    best
+}
+
+/// Which end of the list a program jumped out of
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Exit {
+    Front,
+    Back,
+}
+
+/// Where a run through the jump list spent its time
+#[derive(Debug, PartialEq)]
+struct Stats {
+    /// Number of jumps taken from each index
+    visits: Vec<u64>,
+    /// None if the list wasn't escaped
+    exit: Option<Exit>,
+}
+
+impl Stats {
+    fn new(len: usize) -> Stats {
+        Stats {
+            visits: vec![0; len],
+            exit: None,
+        }
+    }
+
+    /// Updates the stats with a single jump (see run())
+    fn record(&mut self, step: &Step) {
+        self.visits[step.index] += 1;
+        let target = step.index as i64 + i64::from(step.old);
+        if target < 0 {
+            self.exit = Some(Exit::Front);
+        } else if target >= self.visits.len() as i64 {
+            self.exit = Some(Exit::Back);
+        }
+    }
+
+    /// Which end the list was left from
+    fn exit_side(&self) -> &str {
+        match self.exit {
+            Some(Exit::Front) => "front",
+            Some(Exit::Back) => "back",
+            None => "none",
+        }
+    }
+
+    fn exit_summary(&self) -> String {
+        format!("exit: {}", self.exit_side())
+    }
+
+    /// Aligned table of every instruction's final offset and visits
+    fn table(&self, jumps: &[i32]) -> String {
+        let total = self.visits.iter().sum::<u64>().max(1) as f64;
+        let mut table = format!(
+            "{:>8} {:>8} {:>12} {:>7}\n",
+            "index", "offset", "visits", "share"
+        );
+        for (i, (&offset, &visits)) in jumps.iter().zip(self.visits.iter()).enumerate() {
+            table += &format!(
+                "{:>8} {:>8} {:>12} {:>6.2}%\n",
+                i,
+                offset,
+                visits,
+                100.0 * visits as f64 / total
+            );
+        }
+        table + &self.exit_summary() + "\n"
+    }
+
+    /// Same contents as table(), as CSV. The exit side is repeated on
+    /// every row, so each row can be read on its own.
+    fn csv(&self, jumps: &[i32]) -> String {
+        let mut csv = String::from("index,offset,visits,exit\n");
+        for (i, (&offset, &visits)) in jumps.iter().zip(self.visits.iter()).enumerate() {
+            csv += &format!("{},{},{},{}\n", i, offset, visits, self.exit_side());
+        }
+        csv
+    }
+
+    /// A strip of at most `width` colored cells (ANSI 256-color), from
+    /// blue (few visits) to red (most visits). Long lists are split into
+    /// `width` evenly sized buckets and visits are summed per bucket. The
+    /// colors use a log scale, since a few instructions usually take
+    /// almost all of the jumps.
+    fn heat_strip(&self, width: usize) -> String {
+        const PALETTE: [u8; 12] = [17, 19, 21, 27, 33, 39, 45, 49, 118, 190, 208, 196];
+
+        let width = width.max(1);
+        let bucket = self.visits.len().div_ceil(width);
+        let buckets: Vec<u64> = self
+            .visits
+            .chunks(bucket.max(1))
+            .map(|chunk| chunk.iter().sum())
+            .collect();
+        let max = (*buckets.iter().max().unwrap_or(&0) as f64).ln_1p();
+
+        let mut strip = String::new();
+        for &visits in &buckets {
+            let heat = if max > 0.0 {
+                (visits as f64).ln_1p() / max
+            } else {
+                0.0
+            };
+            let color = PALETTE[(heat * (PALETTE.len() - 1) as f64).round() as usize];
+            strip += &format!("\x1b[48;5;{}m \x1b[0m", color);
+        }
+        format!(
+            "{}\n{} instruction(s) per cell, max {} visits per cell, {}\n",
+            strip,
+            bucket,
+            buckets.iter().max().unwrap_or(&0),
+            self.exit_summary()
+        )
+    }
+}
+
+/// How --stats are printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StatsFormat {
+    Table,
+    Csv,
+    Heat,
+}
+
+impl StatsFormat {
+    fn from_str(s: &str) -> Option<StatsFormat> {
+        match s {
+            "table" => Some(StatsFormat::Table),
+            "csv" => Some(StatsFormat::Csv),
+            "heat" => Some(StatsFormat::Heat),
+            _ => None,
+        }
+    }
+}
+
+/// Full state of a jump program (the offsets and the current index). A
+/// running hash of the offsets is kept up to date on every jump, so two
+/// states can almost always be told apart without comparing every offset.
+/// Once the program escapes, the state never changes again, so escaping
+/// looks like a loop of length 1 to cycle detection.
+#[derive(Clone, Debug)]
+struct Machine {
+    jumps: Vec<i32>,
+    index: usize,
+    hash: u64,
+}
+
+impl Machine {
+    fn new(jumps: Vec<i32>, start: usize) -> Machine {
+        let hash = jumps
+            .iter()
+            .enumerate()
+            .fold(0, |hash, (i, &offset)| hash ^ offset_hash(i, offset));
+        Machine {
+            jumps,
+            index: start,
+            hash,
+        }
+    }
+
+    fn escaped(&self) -> bool {
+        self.index >= self.jumps.len()
+    }
+
+    /// Takes a single jump. Returns false if it jumped out of the list (or
+    /// had already escaped).
+    fn step(&mut self, rule: Rule) -> bool {
+        if self.escaped() {
+            return false;
+        }
+        let old = self.jumps[self.index];
+        match take_jump(&mut self.jumps, self.index, rule) {
+            Some(next_index) => {
+                let new = self.jumps[self.index];
+                self.hash ^= offset_hash(self.index, old) ^ offset_hash(self.index, new);
+                self.index = next_index;
+                true
+            }
+            None => {
+                self.index = self.jumps.len();
+                false
+            }
+        }
+    }
+}
+
+impl PartialEq for Machine {
+    fn eq(&self, other: &Machine) -> bool {
+        self.index == other.index && self.hash == other.hash && self.jumps == other.jumps
     }
 }
 
-/// Counts the number of jumps needed to break from the list
-fn count_jumps(mut jumps: Vec<i32>) -> u32 {
-    let (mut count, mut index) = (0, 0);
-    while let Some(next_index) = take_jump(&mut jumps, index) {
-        count += 1;
-        index = next_index;
+impl Eq for Machine {}
+
+impl Hash for Machine {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.hash.hash(state);
+    }
+}
+
+/// Hash of a single offset at a given index. The hash of a whole list is
+/// the XOR of these, so it can be updated one offset at a time.
+fn offset_hash(index: usize, offset: i32) -> u64 {
+    splitmix64((index as u64) << 32 | u64::from(offset as u32))
+}
+
+/// Runs the program with Brent's cycle detection, which only ever keeps
+/// three copies of the state around (the initial state, a tortoise and
+/// a hare). Returns Escaped() with the same count as count_jumps() if the
+/// program terminates, or Loops otherwise.
+fn detect_loop(jumps: Vec<i32>, start: usize, rule: Rule) -> Outcome {
+    let machine = Machine::new(jumps, start);
+    let cycle = cycle::detect(
+        machine,
+        |machine| {
+            machine.step(rule);
+        },
+        Method::Brent,
+        false,
+    );
+
+    if cycle.entry.escaped() {
+        Outcome::Escaped(cycle.start)
+    } else {
+        Outcome::Loops {
+            start: cycle.start,
+            length: cycle.length,
+        }
     }
-    count + 1 // Last jump isn't counted in the loop
 }
 
 fn print_usage() {
-    println!("Day 5: High Entropy Passphrase");
+    println!("Day 5: A Maze of Twisty Trampolines, All Alike");
     println!("Usage:");
-    println!("05 <input_file>");
-    println!("  <input_file> - Jump input file (newline-separated");
+    println!("05 [--strange / --rule <rule>] [--start <index>] [--trace <file>]");
+    println!("   [--max-steps <n>] [--stats <format>] [--detect-loops] [--reference]");
+    println!("   <input_file>");
+    println!("  --strange       - Decrement offsets of three or more (part 2)");
+    println!("  --rule <rule>   - How offsets change after a jump (default: increment)");
+    println!("                      increment, strange, negate,");
+    println!("                      saturate:<max>  Increment up to max");
+    println!("                      wrap:<m>        Increment, wrapping into [-m, m)");
+    println!("                    or an expression in the offset x, e.g.");
+    println!("                      x+1, -x, 2*x-1, x>=3?x-1:x+1");
+    println!("  --trace <file>  - Write every jump to a file (CSV: step,index,old,new)");
+    println!(
+        "  --max-steps <n> - Give up after n jumps (exit status {})",
+        EXIT_MAX_STEPS
+    );
+    println!("  --stats <format> - Print visits per instruction, final offsets and");
+    println!("                     which end the list was left from, as a table,");
+    println!("                     csv or an ANSI heat strip (table / csv / heat)");
+    println!("  --detect-loops  - Check whether the program loops forever (exit");
+    println!(
+        "                    status {}). Can't be used with --trace, --stats",
+        EXIT_LOOPS
+    );
+    println!("                    or --max-steps.");
+    println!("  --start <index> - Index of the first jump (default: 0)");
+    println!("  --reference     - Count jumps with the simple (slower) interpreter");
+    println!("  <input_file>    - Jump input file (newline-separated)");
+    println!("05 generate [--strange / --rule <rule>] [--seed <n>] [--output <file>]");
+    println!("   --length <n> --steps <n>");
+    println!("  --length <n>    - Number of jumps in the generated list");
+    println!("  --steps <n>     - Roughly (within 10%) how many steps it should take");
+    println!("                    to escape. Fails if no list gets that close.");
+    println!("  --seed <n>      - Seed for the random list (default: 0)");
+    println!("  --output <file> - Write the list to a file instead of stdout");
+}
+
+/// Exit status when --max-steps is reached before escaping the list
+const EXIT_MAX_STEPS: i32 = 2;
+
+/// Maximum number of cells in a --stats heat strip
+const HEAT_STRIP_WIDTH: usize = 80;
+
+/// Exit status when --detect-loops finds that the list is never escaped
+const EXIT_LOOPS: i32 = 3;
+
+struct Options {
+    rule: Rule,
+    max_steps: Option<u64>,
+    start: usize,
+    trace: Option<String>,
+    detect_loops: bool,
+    stats: Option<StatsFormat>,
+    reference: bool,
+    filename: String,
+}
+
+/// Parses the command line. Err(None) means the usage should be printed.
+fn parse_args(args: &[String]) -> Result<Options, Option<String>> {
+    let mut options = Options {
+        rule: Rule::INCREMENT,
+        max_steps: None,
+        start: 0,
+        trace: None,
+        detect_loops: false,
+        stats: None,
+        reference: false,
+        filename: String::new(),
+    };
+    let mut filename = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strange" => options.rule = Rule::STRANGE,
+            "--rule" => options.rule = Rule::from_str(args.next().ok_or(None)?)?,
+            "--trace" => options.trace = Some(args.next().ok_or(None)?.clone()),
+            "--detect-loops" => options.detect_loops = true,
+            "--start" => {
+                let start = args.next().ok_or(None)?;
+                options.start = start
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid start index '{}'", start))?;
+            }
+            "--reference" => options.reference = true,
+            "--stats" => {
+                let format = args.next().ok_or(None)?;
+                options.stats = Some(
+                    StatsFormat::from_str(format)
+                        .ok_or_else(|| format!("Unknown stats format '{}'", format))?,
+                );
+            }
+            "--max-steps" => {
+                let steps = args.next().ok_or(None)?;
+                options.max_steps = Some(
+                    steps
+                        .parse::<u64>()
+                        .map_err(|_| format!("Invalid step count '{}'", steps))?,
+                );
+            }
+            _ if filename.is_none() => filename = Some(arg.clone()),
+            _ => return Err(None),
+        }
+    }
+
+    if options.detect_loops
+        && (options.trace.is_some() || options.max_steps.is_some() || options.stats.is_some())
+    {
+        return Err(Some(String::from(
+            "--detect-loops can't be used with --trace, --max-steps or --stats",
+        )));
+    }
+
+    options.filename = filename.ok_or(None)?;
+    Ok(options)
 }
 
 #[derive(Debug)]
@@ -62,36 +814,249 @@ fn jumps_from_file(filename: String) -> Result<Vec<i32>, InputError> {
     }
 }
 
+/// Options for generating jump lists
+struct GenerateOptions {
+    rule: Rule,
+    len: usize,
+    steps: u64,
+    seed: usize,
+    output: Option<String>,
+}
+
+/// Parses the command line after "generate". Err(None) means the usage
+/// should be printed.
+fn parse_generate_args(args: &[String]) -> Result<GenerateOptions, Option<String>> {
+    let mut options = GenerateOptions {
+        rule: Rule::INCREMENT,
+        len: 0,
+        steps: 0,
+        seed: 0,
+        output: None,
+    };
+    let (mut len, mut steps) = (None, None);
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(None);
+        let number = |value: &String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| Some(format!("Expected a number, got '{}'", value)))
+        };
+        match arg.as_str() {
+            "--strange" => options.rule = Rule::STRANGE,
+            "--rule" => options.rule = Rule::from_str(value()?)?,
+            "--length" => len = Some(number(value()?)? as usize),
+            "--steps" => steps = Some(number(value()?)?),
+            "--seed" => options.seed = number(value()?)? as usize,
+            "--output" => options.output = Some(value()?.clone()),
+            _ => return Err(None),
+        }
+    }
+
+    options.len = len.ok_or(None)?;
+    options.steps = steps.ok_or(None)?;
+    Ok(options)
+}
+
+fn generate_main(args: &[String]) {
+    let options = match parse_generate_args(args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let (jumps, steps) = generate_jumps(options.len, options.steps, options.rule, options.seed);
+    // Anything within 10% counts as roughly the target
+    if step_error(steps, options.steps) > options.steps / 10 {
+        match steps {
+            Some(steps) => println!(
+                "Error: Couldn't generate {} jumps escaping after about {} steps \
+                 (the closest took {} steps)",
+                options.len, options.steps, steps
+            ),
+            None => println!(
+                "Error: Couldn't generate {} jumps escaping after about {} steps \
+                 (none of the attempts escaped within {} steps)",
+                options.len,
+                options.steps,
+                options.steps.saturating_mul(2)
+            ),
+        }
+        exit(-1);
+    }
+
+    let contents: String = jumps.iter().map(|jump| format!("{}\n", jump)).collect();
+    let result = match options.output {
+        Some(ref filename) => {
+            File::create(filename).and_then(|mut file| file.write_all(contents.as_bytes()))
+        }
+        None => io::stdout().write_all(contents.as_bytes()),
+    };
+    if let Err(e) = result {
+        println!("IO Error: {}", e);
+        exit(-1);
+    }
+
+    // The summary goes to stderr so the list itself can be piped
+    eprintln!(
+        "Generated {} jumps, escaping after {} steps",
+        jumps.len(),
+        steps.unwrap_or(0)
+    );
+}
+
 fn main() {
-    // Usage is one extra arg (input filename)
-    if let (2, Some(filename)) = (args().count(), args().nth(1)) {
-        match jumps_from_file(filename) {
-            Ok(jumps) => println!("{}", count_jumps(jumps)),
+    let args: Vec<String> = args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("generate") {
+        return generate_main(&args[1..]);
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let mut jumps = match jumps_from_file(options.filename) {
+        Ok(jumps) => jumps,
+
+        Err(InputError::Io(e)) => {
+            println!("IO Error: {}", e);
+            exit(-1);
+        }
+
+        Err(InputError::Parse(e)) => {
+            println!("Parsing Error: {}", e);
+            exit(-1);
+        }
+    };
 
-            Err(InputError::Io(e)) => {
-                println!("IO Error: {}", e);
+    if options.start >= jumps.len() {
+        println!(
+            "Error: Start index {} is outside the list ({} jumps)",
+            options.start,
+            jumps.len()
+        );
+        exit(-1);
+    }
+
+    if options.detect_loops {
+        return print_outcome(&detect_loop(jumps, options.start, options.rule), &[]);
+    }
+
+    if options.trace.is_none() && options.max_steps.is_none() && options.stats.is_none() {
+        let count = if options.reference {
+            count_jumps(jumps, options.start, options.rule)
+        } else {
+            count_jumps_fast(jumps, options.start, options.rule)
+        };
+        return match count {
+            Some(count) => println!("{}", count),
+            None => print_outcome(&Outcome::Overflow { index: 0 }, &[]),
+        };
+    }
+
+    let mut trace = match options.trace {
+        Some(filename) => match File::create(&filename) {
+            Ok(file) => {
+                let mut trace = BufWriter::new(file);
+                let _ = writeln!(trace, "step,index,old,new");
+                Some(trace)
+            }
+            Err(e) => {
+                println!("IO Error ({}): {}", filename, e);
                 exit(-1);
             }
+        },
+        None => None,
+    };
 
-            Err(InputError::Parse(e)) => {
-                println!("Parsing Error: {}", e);
-                exit(-1);
+    // Write errors are remembered rather than stopping the run
+    let mut trace_result = Ok(());
+    let mut stats = Stats::new(jumps.len());
+    let outcome = run(
+        &mut jumps,
+        options.start,
+        options.rule,
+        options.max_steps,
+        |step| {
+            stats.record(&step);
+            if let (Some(trace), &Ok(())) = (trace.as_mut(), &trace_result) {
+                trace_result = writeln!(
+                    trace,
+                    "{},{},{},{}",
+                    step.number, step.index, step.old, step.new
+                );
             }
-        }
-    } else {
-        print_usage();
+        },
+    );
+    if let Err(e) = trace_result.and_then(|_| trace.map_or(Ok(()), |mut t| t.flush())) {
+        println!("IO Error (trace): {}", e);
         exit(-1);
     }
+
+    match options.stats {
+        Some(StatsFormat::Table) => print!("{}", stats.table(&jumps)),
+        Some(StatsFormat::Csv) => print!("{}", stats.csv(&jumps)),
+        Some(StatsFormat::Heat) => print!("{}", stats.heat_strip(HEAT_STRIP_WIDTH)),
+        None => {}
+    }
+    print_outcome(&outcome, &jumps);
+}
+
+/// Prints the result of a run, exiting with the matching status if the
+/// list wasn't escaped
+fn print_outcome(outcome: &Outcome, jumps: &[i32]) {
+    match *outcome {
+        Outcome::Escaped(count) => println!("{}", count),
+        Outcome::Stopped { steps, index } => {
+            println!(
+                "Stopped after {} steps without escaping (at index {}, offset {})",
+                steps, index, jumps[index]
+            );
+            exit(EXIT_MAX_STEPS);
+        }
+        Outcome::Loops { start, length } => {
+            println!(
+                "Loops forever: the loop begins after {} steps and is {} steps long",
+                start, length
+            );
+            exit(EXIT_LOOPS);
+        }
+        Outcome::Overflow { .. } => {
+            println!("Error: More than {} jumps without escaping", u64::MAX);
+            exit(-1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::OnceLock;
+    use test::Bencher;
+
+    fn seeded_jumps(len: usize, seed: usize) -> Vec<i32> {
+        random_jumps(&mut StdRng::from_seed(&[seed][..]), len, 1.0)
+    }
 
     #[test]
     fn single_step() {
         let mut input = vec![1, 2, 0, -1];
-        assert_eq!(take_jump(&mut input, 1), Some(3));
+        assert_eq!(take_jump(&mut input, 1, Rule::INCREMENT), Some(3));
         assert_eq!(input, vec![1, 3, 0, -1]);
     }
 
@@ -99,19 +1064,389 @@ mod tests {
     fn single_step_out() {
         let mut input = vec![1, 3, 0, -1];
         let original = input.clone();
-        assert_eq!(take_jump(&mut input, 1), None);
+        assert_eq!(take_jump(&mut input, 1, Rule::INCREMENT), None);
         assert_eq!(input, original);
     }
 
     #[test]
     fn aoc_example_first_step() {
         let mut input = vec![0, 3, 0, 1, -3];
-        assert_eq!(take_jump(&mut input, 0), Some(0));
+        assert_eq!(take_jump(&mut input, 0, Rule::INCREMENT), Some(0));
         assert_eq!(input, vec![1, 3, 0, 1, -3]);
     }
 
     #[test]
     fn aoc_example() {
-        assert_eq!(count_jumps(vec![0, 3, 0, 1, -3]), 5);
+        assert_eq!(
+            count_jumps(vec![0, 3, 0, 1, -3], 0, Rule::INCREMENT),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn named_rules() {
+        assert_eq!(Rule::from_str("increment"), Ok(Rule::INCREMENT));
+        assert_eq!(Rule::from_str("strange"), Ok(Rule::STRANGE));
+        assert_eq!(Rule::from_str("negate").unwrap().apply(-4), 4);
+        assert_eq!(Rule::from_str("saturate:2").unwrap().apply(1), 2);
+        assert_eq!(Rule::from_str("saturate:2").unwrap().apply(2), 2);
+        assert_eq!(Rule::from_str("saturate:2").unwrap().apply(5), 5);
+        assert_eq!(Rule::from_str("wrap:3").unwrap().apply(2), -3);
+        assert_eq!(Rule::from_str("wrap:3").unwrap().apply(-3), -2);
+        assert!(Rule::from_str("wrap:0").is_err());
+        assert!(Rule::from_str("saturate").is_err());
+    }
+
+    #[test]
+    fn rule_expressions() {
+        assert_eq!(Rule::from_str("x+1"), Ok(Rule::INCREMENT));
+        assert_eq!(Rule::from_str("x >= 3 ? x - 1 : x + 1"), Ok(Rule::STRANGE));
+        assert_eq!(Rule::from_str("-x"), Rule::from_str("negate"));
+        assert_eq!(Rule::from_str("-2*x+7").unwrap().apply(3), 1);
+        assert_eq!(Rule::from_str("3x").unwrap().apply(3), 9);
+        assert_eq!(Rule::from_str("x").unwrap().apply(3), 3);
+        assert_eq!(Rule::from_str("x<0?-x:x").unwrap().apply(-3), 3);
+        assert_eq!(Rule::from_str("x!=1?1:0").unwrap().apply(5), 1);
+        assert_eq!(Rule::from_str("2*x").unwrap().apply(i32::MAX), i32::MAX);
+        assert!(Rule::from_str("x+").is_err());
+        assert!(Rule::from_str("y+1").is_err());
+        assert!(Rule::from_str("x>3?x").is_err());
+    }
+
+    #[test]
+    fn negated_jumps() {
+        // 0 -> 2 -> 0, which now holds -2 and jumps out of the list
+        let rule = Rule::from_str("negate").unwrap();
+        assert_eq!(count_jumps(vec![2, 0, -2, 5], 0, rule), Some(3));
+    }
+
+    #[test]
+    fn traced_run() {
+        let mut steps = Vec::new();
+        let outcome = run(&mut [1, -1, 3], 0, Rule::INCREMENT, None, |step| {
+            steps.push((step.number, step.index, step.old, step.new))
+        });
+        assert_eq!(outcome, Outcome::Escaped(4));
+        assert_eq!(
+            steps,
+            vec![(1, 0, 1, 2), (2, 1, -1, 0), (3, 0, 2, 3), (4, 2, 3, 3)]
+        );
+    }
+
+    #[test]
+    fn max_steps() {
+        let aoc_example = vec![0, 3, 0, 1, -3];
+        let run_until = |max_steps| {
+            run(
+                &mut aoc_example.clone(),
+                0,
+                Rule::INCREMENT,
+                Some(max_steps),
+                |_| {},
+            )
+        };
+        assert_eq!(run_until(2), Outcome::Stopped { steps: 2, index: 1 });
+        assert_eq!(run_until(5), Outcome::Escaped(5));
+
+        // x stays the same forever
+        let rule = Rule::from_str("x").unwrap();
+        assert_eq!(
+            run(&mut [0], 0, rule, Some(1000), |_| {}),
+            Outcome::Stopped {
+                steps: 1000,
+                index: 0
+            }
+        );
+    }
+
+    #[test]
+    fn loop_detection() {
+        // Offsets never change, so [0] loops on the very first state
+        let rule = Rule::from_str("x").unwrap();
+        assert_eq!(
+            detect_loop(vec![0], 0, rule),
+            Outcome::Loops {
+                start: 0,
+                length: 1
+            }
+        );
+
+        // 0 -> 1 -> 2 -> 1 -> 2 ...
+        assert_eq!(
+            detect_loop(vec![1, 1, -1], 0, rule),
+            Outcome::Loops {
+                start: 1,
+                length: 2
+            }
+        );
+
+        // 0 -> 1 -> 3 -> 2 -> 1 -> 3 -> 2 ...
+        assert_eq!(
+            detect_loop(vec![1, 2, -1, -1], 0, rule),
+            Outcome::Loops {
+                start: 1,
+                length: 3
+            }
+        );
+
+        // The first jump changes the list, then index 1 never moves
+        let rule = Rule::from_str("negate").unwrap();
+        assert_eq!(
+            detect_loop(vec![1, 0], 0, rule),
+            Outcome::Loops {
+                start: 1,
+                length: 1
+            }
+        );
+    }
+
+    #[test]
+    fn loop_detection_escapes() {
+        let aoc_example = vec![0, 3, 0, 1, -3];
+        assert_eq!(
+            detect_loop(aoc_example.clone(), 0, Rule::INCREMENT),
+            Outcome::Escaped(5)
+        );
+        assert_eq!(
+            detect_loop(aoc_example, 0, Rule::STRANGE),
+            Outcome::Escaped(10)
+        );
+        assert_eq!(detect_loop(vec![], 0, Rule::INCREMENT), Outcome::Escaped(0));
+    }
+
+    #[test]
+    fn aoc_example_stats() {
+        let mut jumps = vec![0, 3, 0, 1, -3];
+        let mut stats = Stats::new(jumps.len());
+        run(&mut jumps, 0, Rule::INCREMENT, None, |step| {
+            stats.record(&step)
+        });
+        assert_eq!(
+            stats,
+            Stats {
+                visits: vec![2, 2, 0, 0, 1],
+                exit: Some(Exit::Back),
+            }
+        );
+        assert_eq!(
+            stats.csv(&jumps),
+            "index,offset,visits,exit\n0,2,2,back\n1,4,2,back\n2,0,0,back\n3,1,0,back\n\
+             4,-2,1,back\n"
+        );
+        assert!(stats
+            .table(&jumps)
+            .ends_with("       4       -2            1  20.00%\nexit: back\n"));
+    }
+
+    #[test]
+    fn stats_exit_front() {
+        let mut stats = Stats::new(2);
+        run(&mut [1, -2], 0, Rule::INCREMENT, None, |step| {
+            stats.record(&step)
+        });
+        assert_eq!(stats.exit, Some(Exit::Front));
+
+        let mut stats = Stats::new(1);
+        run(
+            &mut [0],
+            0,
+            Rule::from_str("x").unwrap(),
+            Some(10),
+            |step| stats.record(&step),
+        );
+        assert_eq!(stats.exit, None);
+        assert_eq!(stats.visits, vec![10]);
+        assert_eq!(stats.csv(&[0]), "index,offset,visits,exit\n0,0,10,none\n");
+    }
+
+    #[test]
+    fn heat_strip() {
+        let stats = Stats {
+            visits: vec![0, 1, 100, 0, 5],
+            exit: Some(Exit::Back),
+        };
+        let strip = stats.heat_strip(80);
+        assert_eq!(strip.matches("\x1b[48;5;").count(), 5);
+        assert!(strip.starts_with("\x1b[48;5;17m \x1b[0m"));
+        assert!(strip.contains("\x1b[48;5;196m"));
+        assert!(strip.ends_with("1 instruction(s) per cell, max 100 visits per cell, exit: back\n"));
+
+        // Buckets of two instructions
+        let strip = stats.heat_strip(3);
+        assert_eq!(strip.matches("\x1b[48;5;").count(), 3);
+        assert!(strip.contains("2 instruction(s) per cell, max 100 visits per cell"));
+    }
+
+    #[test]
+    fn start_index() {
+        // Starting at the last jump of the AoC example
+        let aoc_example = vec![0, 3, 0, 1, -3];
+        assert_eq!(
+            count_jumps(aoc_example.clone(), 4, Rule::INCREMENT),
+            Some(8)
+        );
+        assert_eq!(
+            count_jumps_fast(aoc_example.clone(), 4, Rule::INCREMENT),
+            Some(8)
+        );
+        assert_eq!(
+            detect_loop(aoc_example.clone(), 4, Rule::INCREMENT),
+            Outcome::Escaped(8)
+        );
+        assert_eq!(count_jumps(aoc_example, 5, Rule::INCREMENT), Some(0));
+
+        // Both machines start at index 1, where the offset never changes
+        let rule = Rule::from_str("x").unwrap();
+        assert_eq!(
+            detect_loop(vec![5, 0], 1, rule),
+            Outcome::Loops {
+                start: 0,
+                length: 1
+            }
+        );
+    }
+
+    #[test]
+    fn large_jumps() {
+        // Jumps that would overflow i32 index arithmetic
+        let mut input = vec![i32::MAX, i32::MIN, 0];
+        assert_eq!(take_jump(&mut input, 0, Rule::INCREMENT), None);
+        assert_eq!(take_jump(&mut input, 1, Rule::INCREMENT), None);
+        assert_eq!(count_jumps(vec![1, i32::MIN], 0, Rule::INCREMENT), Some(2));
+        assert_eq!(
+            count_jumps_fast(vec![1, i32::MIN], 0, Rule::INCREMENT),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn generated_step_counts() {
+        for &(target, rule) in &[(20_000, Rule::INCREMENT), (200_000, Rule::STRANGE)] {
+            let (jumps, steps) = generate_jumps(500, target, rule, 1);
+            assert_eq!(jumps.len(), 500);
+            assert_eq!(count_jumps(jumps.clone(), 0, rule), steps);
+
+            let steps = steps.unwrap();
+            assert!(steps > target * 9 / 10 && steps < target * 11 / 10);
+            assert_eq!(generate_jumps(500, target, rule, 1).0, jumps);
+        }
+    }
+
+    #[test]
+    fn generated_extremes() {
+        // Few steps for a long list needs forward jumps, and many steps
+        // needs jumps back to the start
+        for &(len, target, rule) in &[(1000, 50, Rule::STRANGE), (1000, 400_000, Rule::INCREMENT)] {
+            let (jumps, steps) = generate_jumps(len, target, rule, 0);
+            assert_eq!(count_jumps(jumps, 0, rule), steps);
+            assert!(step_error(steps, target) <= target / 10);
+        }
+
+        // Under increment, each of n jumps is taken at most n times
+        let (_, steps) = generate_jumps(50, 5000, Rule::INCREMENT, 0);
+        assert!(steps.unwrap() <= 50 * 50);
+
+        // Step counts beyond i64::MAX don't wrap around
+        assert_eq!(step_error(Some(1), u64::MAX), u64::MAX - 1);
+        assert_eq!(step_error(Some(u64::MAX), 0), u64::MAX);
+    }
+
+    #[test]
+    fn generated_never_escaping() {
+        // Nothing escapes under "x" once a zero offset is reached, but the
+        // search still finishes
+        let (jumps, _) = generate_jumps(100, 1000, Rule::from_str("x").unwrap(), 3);
+        assert_eq!(jumps.len(), 100);
+    }
+
+    #[test]
+    fn strange_single_step() {
+        let mut input = vec![0, 3, 0, 1, -3];
+        assert_eq!(take_jump(&mut input, 1, Rule::STRANGE), Some(4));
+        assert_eq!(input, vec![0, 2, 0, 1, -3]);
+    }
+
+    #[test]
+    fn aoc_example_strange() {
+        let mut input = vec![0, 3, 0, 1, -3];
+        let mut index = 0;
+        while let Some(next_index) = take_jump(&mut input, index, Rule::STRANGE) {
+            index = next_index;
+        }
+        // AoC shows [2, 3, 2, 3, -1], but the final jump (out of the list
+        // from index 3) doesn't update its offset here
+        assert_eq!(input, vec![2, 3, 2, 2, -1]);
+        assert_eq!(
+            count_jumps(vec![0, 3, 0, 1, -3], 0, Rule::STRANGE),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn fast_matches_reference() {
+        let rules = [
+            "increment",
+            "strange",
+            "x>=2?x-2:x+1",
+            "saturate:5",
+            "wrap:40",
+        ];
+        for seed in 0..20 {
+            let jumps = seeded_jumps(50 + seed * 10, seed);
+            for rule in rules.iter().map(|rule| Rule::from_str(rule).unwrap()) {
+                assert_eq!(
+                    count_jumps_fast(jumps.clone(), 0, rule),
+                    count_jumps(jumps.clone(), 0, rule)
+                );
+            }
+        }
+        assert_eq!(
+            count_jumps_fast(vec![0, 3, 0, 1, -3], 0, Rule::STRANGE),
+            Some(10)
+        );
+        assert_eq!(count_jumps_fast(vec![], 0, Rule::STRANGE), Some(0));
+    }
+
+    #[bench]
+    fn bench_reference_strange(b: &mut Bencher) {
+        let jumps = seeded_jumps(1000, 5);
+        b.iter(|| count_jumps(jumps.clone(), 0, Rule::STRANGE));
+    }
+
+    #[bench]
+    fn bench_fast_strange(b: &mut Bencher) {
+        let jumps = seeded_jumps(1000, 5);
+        b.iter(|| count_jumps_fast(jumps.clone(), 0, Rule::STRANGE));
+    }
+
+    #[bench]
+    fn bench_reference_increment(b: &mut Bencher) {
+        let jumps = seeded_jumps(1000, 5);
+        b.iter(|| count_jumps(jumps.clone(), 0, Rule::INCREMENT));
+    }
+
+    #[bench]
+    fn bench_fast_increment(b: &mut Bencher) {
+        let jumps = seeded_jumps(1000, 5);
+        b.iter(|| count_jumps_fast(jumps.clone(), 0, Rule::INCREMENT));
+    }
+
+    /// 10^5 jumps escaping after about 10^6 steps under the strange rule,
+    /// generated once and shared by the benchmarks below
+    fn large_list() -> &'static [i32] {
+        static JUMPS: OnceLock<Vec<i32>> = OnceLock::new();
+        JUMPS.get_or_init(|| generate_jumps(100_000, 1_000_000, Rule::STRANGE, 5).0)
+    }
+
+    #[bench]
+    fn bench_reference_strange_large(b: &mut Bencher) {
+        let jumps = large_list();
+        b.iter(|| count_jumps(jumps.to_vec(), 0, Rule::STRANGE));
+    }
+
+    #[bench]
+    fn bench_fast_strange_large(b: &mut Bencher) {
+        let jumps = large_list();
+        b.iter(|| count_jumps_fast(jumps.to_vec(), 0, Rule::STRANGE));
     }
 }
@@ -1,22 +1,159 @@
-use std::collections::HashSet;
+#![feature(test)]
+
+extern crate advent_2017;
+extern crate rand;
+extern crate test;
+
+use advent_2017::cycle::{self, Method};
+use advent_2017::hash::splitmix64;
+use rand::{Rng, SeedableRng, StdRng};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env::args;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::num::IntErrorKind;
 use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-fn max_bank(banks: &Vec<u32>) -> (usize, u32) {
-    banks.iter().enumerate().fold(
-        (0, 0),
-        |(max_i, max_v), (i, &v)| {
-            if v > max_v {
-                (i, v)
-            } else {
-                (max_i, max_v)
-            }
-        },
-    )
+/// Which bank is emptied when several hold the most blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TieBreak {
+    Lowest,
+    Highest,
+    /// Picks one of the tied banks pseudo-randomly. The choice is a hash of
+    /// the seed and the whole state, so a state is always redistributed the
+    /// same way and cycle detection still works.
+    Random(u64),
+}
+
+impl TieBreak {
+    fn from_str(s: &str) -> Result<TieBreak, String> {
+        match s {
+            "lowest" => Ok(TieBreak::Lowest),
+            "highest" => Ok(TieBreak::Highest),
+            _ if s.starts_with("random:") => s["random:".len()..]
+                .parse::<u64>()
+                .map(TieBreak::Random)
+                .map_err(|_| format!("Invalid random seed in '{}'", s)),
+            _ => Err(format!("Unknown tie-break '{}'", s)),
+        }
+    }
+}
+
+/// Which way blocks are handed out
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    Forward,
+    Backward,
 }
 
-fn redistribute(banks: &mut Vec<u32>) {
-    let (max_index, max_value) = max_bank(&banks);
+impl Direction {
+    fn from_str(s: &str) -> Result<Direction, String> {
+        match s {
+            "forward" => Ok(Direction::Forward),
+            "backward" => Ok(Direction::Backward),
+            _ => Err(format!("Unknown direction '{}'", s)),
+        }
+    }
+}
+
+/// Which bank gets the first of the leftover blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Start {
+    /// The bank after the emptied one
+    Next,
+    /// The emptied bank itself
+    Same,
+    /// The first bank in the direction blocks are handed out (the first
+    /// bank going forward, the last going backward)
+    First,
+}
+
+impl Start {
+    fn from_str(s: &str) -> Result<Start, String> {
+        match s {
+            "next" => Ok(Start::Next),
+            "same" => Ok(Start::Same),
+            "first" => Ok(Start::First),
+            _ => Err(format!("Unknown distribution start '{}'", s)),
+        }
+    }
+}
+
+/// How a redistribution is carried out. The default is the puzzle's
+/// policy: empty the lowest of the largest banks, then hand out blocks
+/// forward starting at the next bank.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Policy {
+    tie_break: TieBreak,
+    direction: Direction,
+    start: Start,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            tie_break: TieBreak::Lowest,
+            direction: Direction::Forward,
+            start: Start::Next,
+        }
+    }
+}
+
+impl Policy {
+    /// Index of the bank `steps` banks away from `index` in the direction
+    /// blocks are handed out, wrapping around
+    fn advance(&self, index: usize, steps: usize, len: usize) -> usize {
+        match self.direction {
+            Direction::Forward => (index + steps) % len,
+            Direction::Backward => (index + len - steps % len) % len,
+        }
+    }
+
+    /// The bank that gets the first leftover block after emptying `chosen`
+    fn first_bank(&self, chosen: usize, len: usize) -> usize {
+        match (self.start, self.direction) {
+            (Start::Next, _) => self.advance(chosen, 1, len),
+            (Start::Same, _) => chosen,
+            (Start::First, Direction::Forward) => 0,
+            (Start::First, Direction::Backward) => len - 1,
+        }
+    }
+}
+
+/// Finds the bank to redistribute, returning its index and block count
+fn max_bank(banks: &[u64], tie_break: TieBreak) -> (usize, u64) {
+    let max_value = banks.iter().cloned().max().unwrap_or(0);
+    let mut tied = banks
+        .iter()
+        .enumerate()
+        .filter(|&(_, &v)| v == max_value)
+        .map(|(i, _)| i);
+
+    let index = match tie_break {
+        TieBreak::Lowest => tied.next(),
+        TieBreak::Highest => tied.next_back(),
+        TieBreak::Random(seed) => {
+            let tied: Vec<usize> = tied.collect();
+            let pick = state_hash(seed, banks) % tied.len() as u64;
+            Some(tied[pick as usize])
+        }
+    };
+    (index.unwrap_or(0), max_value)
+}
+
+/// Hash of a seed and a list of banks (splitmix64 on each bank in turn)
+fn state_hash(seed: u64, banks: &[u64]) -> u64 {
+    banks.iter().fold(seed, |hash, &v| splitmix64(hash ^ v))
+}
+
+/// Redistributes the blocks of one bank. Returns the index of the bank
+/// that was emptied and the number of blocks moved.
+fn redistribute(banks: &mut [u64], policy: &Policy) -> (usize, u64) {
+    let (max_index, max_value) = max_bank(banks, policy.tie_break);
     let len = banks.len();
 
     // Clear out the bank being redistributed
@@ -24,103 +161,1548 @@ fn redistribute(banks: &mut Vec<u32>) {
 
     // All banks will add at least (max_value / len) blocks.
     for val in banks.iter_mut() {
-        *val += max_value / len as u32;
+        *val += max_value / len as u64;
     }
 
     // After the initial distribution of (max_value / len) blocks,
     // it's guaranteed that the remaining blocks, (max_value % len),
-    // will be less than len. However, these blocks are distributed
-    // starting at the bank following banks[max_index] and may wrap
-    // back to the start. There's probably a graceful way to do this,
-    // but the simplest way is to iterate from the first bank after
-    // banks[max_index], decrementing the remaining blocks and cycling
-    // back to the start if necessary.
-    let mut remaining_blocks = max_value % len as u32;
-    for val in banks.iter_mut().skip(max_index + 1) {
-        if remaining_blocks > 0 {
-            *val += 1;
-            remaining_blocks -= 1;
+    // will be less than len, so each of them goes to a different bank,
+    // starting from the policy's first bank and wrapping around.
+    let first = policy.first_bank(max_index, len);
+    for i in 0..(max_value % len as u64) as usize {
+        banks[policy.advance(first, i, len)] += 1;
+    }
+
+    (max_index, max_value)
+}
+
+/// Redistributes until a state repeats. Returns the number of
+/// redistributions before the repeat (part 1) and the size of the loop,
+/// i.e. the number of redistributions between the two occurrences of the
+/// repeated state (part 2).
+fn cycle_count(banks: Vec<u64>, method: Method, policy: &Policy) -> (u64, u64) {
+    let cycle = cycle::detect(
+        banks,
+        |banks| {
+            redistribute(banks, policy);
+        },
+        method,
+        false,
+    );
+
+    // The first repeat is the first state after going around the loop once
+    (cycle.start + cycle.length, cycle.length)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TraceFormat {
+    Text,
+    Csv,
+}
+
+impl TraceFormat {
+    fn from_str(s: &str) -> Result<TraceFormat, String> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "csv" => Ok(TraceFormat::Csv),
+            _ => Err(format!("Unknown trace format '{}'", s)),
+        }
+    }
+}
+
+/// Every state from the initial one up to and including the first repeat
+#[derive(Debug, PartialEq)]
+struct Trace {
+    states: Vec<Vec<u64>>,
+    /// Bank emptied and blocks moved for every state but the last
+    moves: Vec<(usize, u64)>,
+    /// Index of the first occurrence of the repeated (last) state
+    loop_start: usize,
+}
+
+impl Trace {
+    fn new(banks: Vec<u64>, policy: &Policy) -> Trace {
+        let (cycles, loop_size) = cycle_count(banks.clone(), Method::HashSet, policy);
+
+        let mut states = vec![banks];
+        let mut moves = Vec::new();
+        for _ in 0..cycles {
+            let mut banks = states[states.len() - 1].clone();
+            moves.push(redistribute(&mut banks, policy));
+            states.push(banks);
+        }
+
+        Trace {
+            states,
+            moves,
+            loop_start: (cycles - loop_size) as usize,
+        }
+    }
+
+    /// Marks the first occurrence of the repeated state and the repeat
+    fn mark(&self, step: usize) -> String {
+        if step == self.loop_start {
+            String::from("loop start")
+        } else if step + 1 == self.states.len() {
+            format!("repeats {}", self.loop_start)
         } else {
-            break;
+            String::new()
         }
     }
 
-    // Handle the overflow case
-    for val in banks.iter_mut() {
-        if remaining_blocks > 0 {
-            *val += 1;
-            remaining_blocks -= 1;
+    fn text(&self) -> String {
+        let value_width = self
+            .states
+            .iter()
+            .flat_map(|banks| banks.iter())
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let banks_width = self.states[0].len() * (value_width + 1) - 1;
+        let step_width = self.states.len().to_string().len().max(4);
+
+        let mut text = format!(
+            "{:>sw$}  {:<bw$}  {:>4}  {:>6}\n",
+            "step",
+            "banks",
+            "bank",
+            "blocks",
+            sw = step_width,
+            bw = banks_width
+        );
+        for (step, banks) in self.states.iter().enumerate() {
+            let banks: Vec<String> = banks
+                .iter()
+                .map(|v| format!("{:>w$}", v, w = value_width))
+                .collect();
+            let (bank, blocks) = match self.moves.get(step) {
+                Some(&(bank, blocks)) => (bank.to_string(), blocks.to_string()),
+                None => (String::new(), String::new()),
+            };
+            let line = format!(
+                "{:>sw$}  {:<bw$}  {:>4}  {:>6}  {}",
+                step,
+                banks.join(" "),
+                bank,
+                blocks,
+                self.mark(step),
+                sw = step_width,
+                bw = banks_width
+            );
+            text += line.trim_end();
+            text += "\n";
+        }
+        text
+    }
+
+    /// Same contents as text(), as CSV with one column per bank
+    fn csv(&self) -> String {
+        let mut csv = String::from("step,bank,blocks,mark");
+        for i in 0..self.states[0].len() {
+            csv += &format!(",b{}", i);
+        }
+        csv += "\n";
+
+        for (step, banks) in self.states.iter().enumerate() {
+            match self.moves.get(step) {
+                Some(&(bank, blocks)) => csv += &format!("{},{},{},", step, bank, blocks),
+                None => csv += &format!("{},,,", step),
+            }
+            csv += &self.mark(step);
+            for v in banks {
+                csv += &format!(",{}", v);
+            }
+            csv += "\n";
+        }
+        csv
+    }
+}
+
+/// Every state that redistribute() turns into `banks`, sorted. There may
+/// be none, or several.
+fn predecessors(banks: &[u64], policy: &Policy) -> Vec<Vec<u64>> {
+    let len = banks.len();
+    let mut found = Vec::new();
+
+    // If bank `chosen` held q * len + r blocks, every bank got q of them
+    // and the r banks starting at the policy's first bank got one more.
+    // The emptied bank ends up with only what it got back, which fixes q,
+    // and taking the blocks back out of the others gives the candidate.
+    for chosen in 0..len {
+        let first = policy.first_bank(chosen, len);
+        for r in 0..len {
+            let mut extra = vec![0; len];
+            for i in 0..r {
+                extra[policy.advance(first, i, len)] = 1;
+            }
+            let q = match banks[chosen].checked_sub(extra[chosen]) {
+                Some(q) => q,
+                None => continue,
+            };
+            let blocks = match q
+                .checked_mul(len as u64)
+                .and_then(|blocks| blocks.checked_add(r as u64))
+            {
+                Some(blocks) => blocks,
+                None => continue,
+            };
+            let candidate: Option<Vec<u64>> = banks
+                .iter()
+                .zip(extra.iter())
+                .enumerate()
+                .map(|(i, (&v, &e))| {
+                    if i == chosen {
+                        Some(blocks)
+                    } else {
+                        q.checked_add(e).and_then(|given| v.checked_sub(given))
+                    }
+                })
+                .collect();
+
+            // The candidate only counts if the policy would actually have
+            // picked `chosen` (and handed out the blocks the same way)
+            if let Some(candidate) = candidate {
+                let mut after = candidate.clone();
+                redistribute(&mut after, policy);
+                if after == banks && !found.contains(&candidate) {
+                    found.push(candidate);
+                }
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+/// Predecessors of a state, and their predecessors, down to a given depth
+#[derive(Debug, PartialEq)]
+struct PredecessorTree {
+    banks: Vec<u64>,
+    /// None if the depth limit was reached before looking
+    predecessors: Option<Vec<PredecessorTree>>,
+}
+
+impl PredecessorTree {
+    fn new(banks: Vec<u64>, policy: &Policy, depth: usize) -> PredecessorTree {
+        let predecessors = if depth > 0 {
+            Some(
+                predecessors(&banks, policy)
+                    .into_iter()
+                    .map(|banks| PredecessorTree::new(banks, policy, depth - 1))
+                    .collect(),
+            )
         } else {
-            break;
+            None
+        };
+        PredecessorTree {
+            banks,
+            predecessors,
+        }
+    }
+
+    /// States in the tree that have no predecessors at all (Garden-of-Eden
+    /// states). Only states above the depth limit are known to qualify.
+    fn gardens_of_eden(&self) -> Vec<&[u64]> {
+        match self.predecessors {
+            Some(ref predecessors) if predecessors.is_empty() => vec![&self.banks],
+            Some(ref predecessors) => predecessors
+                .iter()
+                .flat_map(|tree| tree.gardens_of_eden())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// One state per line, indented by depth
+    fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(0, &mut text);
+        text
+    }
+
+    fn write_text(&self, depth: usize, text: &mut String) {
+        let banks: Vec<String> = self.banks.iter().map(|v| v.to_string()).collect();
+        *text += &"  ".repeat(depth);
+        *text += &banks.join(" ");
+        if self.predecessors == Some(Vec::new()) {
+            *text += "  (no predecessors)";
+        }
+        *text += "\n";
+        for tree in self.predecessors.iter().flat_map(|p| p.iter()) {
+            tree.write_text(depth + 1, text);
+        }
+    }
+}
+
+/// How the initial configurations of a survey are chosen
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sampling {
+    /// `samples` random configurations per size
+    Random { samples: u64, seed: usize },
+    /// Every configuration of each size
+    Exhaustive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SurveyFormat {
+    Histogram,
+    Csv,
+}
+
+impl SurveyFormat {
+    fn from_str(s: &str) -> Result<SurveyFormat, String> {
+        match s {
+            "histogram" => Ok(SurveyFormat::Histogram),
+            "csv" => Ok(SurveyFormat::Csv),
+            _ => Err(format!("Unknown survey format '{}'", s)),
+        }
+    }
+}
+
+/// Most configurations an exhaustive survey will go through per size
+const MAX_EXHAUSTIVE: u64 = 10_000_000;
+
+/// Random configurations handed to a thread at a time. Each chunk has its
+/// own generator, so results don't depend on the number of threads.
+const SAMPLE_CHUNK: u64 = 256;
+
+/// Number of ways to put `blocks` blocks into `banks` banks
+fn configurations(banks: usize, blocks: u64) -> Option<u64> {
+    // C(blocks + banks - 1, banks - 1), one factor at a time
+    let mut count: u128 = 1;
+    for i in 1..banks as u128 {
+        count = count.checked_mul(u128::from(blocks) + i)? / i;
+        if count > u128::from(u64::MAX) {
+            return None;
+        }
+    }
+    Some(count as u64)
+}
+
+/// Calls `f` with every way to put `blocks` blocks into `banks.len()`
+/// banks, after the banks already filled in `banks[..filled]`
+fn for_each_configuration<F>(banks: &mut Vec<u64>, filled: usize, blocks: u64, f: &mut F)
+where
+    F: FnMut(&[u64]),
+{
+    if filled + 1 == banks.len() {
+        banks[filled] = blocks;
+        f(banks);
+        return;
+    }
+    for v in 0..=blocks {
+        banks[filled] = v;
+        for_each_configuration(banks, filled + 1, blocks - v, f);
+    }
+}
+
+/// A uniformly random way to put `blocks` blocks into `len` banks. Each
+/// configuration is a row of `blocks + len - 1` slots where `len - 1` of
+/// them are separators between banks (stars and bars), so picking the
+/// separators' slots uniformly picks a uniform configuration.
+fn random_configuration<R: Rng>(rng: &mut R, len: usize, blocks: u64) -> Vec<u64> {
+    // Floyd's algorithm for picking distinct slots, numbered from 1
+    let slots = blocks + len as u64 - 1;
+    let mut separators = BTreeSet::new();
+    for last in slots - (len as u64 - 1) + 1..=slots {
+        let slot = rng.gen_range(1, last + 1);
+        if !separators.insert(slot) {
+            separators.insert(last);
+        }
+    }
+
+    // Each bank gets the slots between two separators (or the ends)
+    let mut bounds = vec![0];
+    bounds.extend(separators);
+    bounds.push(slots + 1);
+    bounds
+        .windows(2)
+        .map(|pair| pair[1] - pair[0] - 1)
+        .collect()
+}
+
+/// A share of the survey work for one size
+#[derive(Clone, Copy, Debug)]
+enum Job {
+    /// Every configuration whose first bank holds `first` blocks
+    Exhaustive { first: u64 },
+    /// `count` random configurations from chunk number `chunk`
+    Random { chunk: u64, count: u64 },
+}
+
+/// Transient and loop lengths seen for one size, with how often each
+/// length came up
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Distribution {
+    configurations: u64,
+    transients: BTreeMap<u64, u64>,
+    loops: BTreeMap<u64, u64>,
+}
+
+impl Distribution {
+    fn record(&mut self, cycles: u64, loop_size: u64) {
+        self.configurations += 1;
+        *self.transients.entry(cycles - loop_size).or_insert(0) += 1;
+        *self.loops.entry(loop_size).or_insert(0) += 1;
+    }
+}
+
+struct Survey {
+    banks: (usize, usize),
+    blocks: (u64, u64),
+    sampling: Sampling,
+    threads: usize,
+    method: Method,
+    policy: Policy,
+}
+
+impl Survey {
+    /// Runs cycle_count() on every configuration, spread over all threads.
+    /// Returns a distribution per (banks, blocks) size.
+    fn run(&self) -> Result<BTreeMap<(usize, u64), Distribution>, String> {
+        let mut jobs = Vec::new();
+        for banks in self.banks.0..=self.banks.1 {
+            for blocks in self.blocks.0..=self.blocks.1 {
+                match self.sampling {
+                    Sampling::Exhaustive => {
+                        match configurations(banks, blocks) {
+                            Some(count) if count <= MAX_EXHAUSTIVE => (),
+                            _ => {
+                                return Err(format!(
+                                    "Too many configurations of {} banks and {} blocks \
+                                     to survey them all (use --samples)",
+                                    banks, blocks
+                                ))
+                            }
+                        }
+                        let firsts = if banks == 1 { blocks } else { 0 };
+                        for first in firsts..=blocks {
+                            jobs.push((banks, blocks, Job::Exhaustive { first }));
+                        }
+                    }
+                    Sampling::Random { samples, .. } => {
+                        for chunk in 0..samples.div_ceil(SAMPLE_CHUNK) {
+                            let count = SAMPLE_CHUNK.min(samples - chunk * SAMPLE_CHUNK);
+                            jobs.push((banks, blocks, Job::Random { chunk, count }));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Each thread takes the next job until there are none left
+        let next_job = AtomicUsize::new(0);
+        let mut results = BTreeMap::new();
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results: BTreeMap<(usize, u64), Distribution> = BTreeMap::new();
+                        while let Some(&(banks, blocks, job)) =
+                            jobs.get(next_job.fetch_add(1, Ordering::Relaxed))
+                        {
+                            let distribution = results.entry((banks, blocks)).or_default();
+                            self.run_job(banks, blocks, job, distribution);
+                        }
+                        results
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                for (size, distribution) in worker.join().unwrap() {
+                    let total: &mut Distribution = results.entry(size).or_default();
+                    total.configurations += distribution.configurations;
+                    for (length, count) in distribution.transients {
+                        *total.transients.entry(length).or_insert(0) += count;
+                    }
+                    for (length, count) in distribution.loops {
+                        *total.loops.entry(length).or_insert(0) += count;
+                    }
+                }
+            }
+        });
+        Ok(results)
+    }
+
+    fn run_job(&self, len: usize, blocks: u64, job: Job, distribution: &mut Distribution) {
+        let mut record = |banks: &[u64]| {
+            let (cycles, loop_size) = cycle_count(banks.to_vec(), self.method, &self.policy);
+            distribution.record(cycles, loop_size);
+        };
+        match (job, self.sampling) {
+            (Job::Exhaustive { first }, _) => {
+                let mut banks = vec![0; len];
+                banks[0] = first;
+                if len == 1 {
+                    record(&banks);
+                } else {
+                    for_each_configuration(&mut banks, 1, blocks - first, &mut record);
+                }
+            }
+            (Job::Random { chunk, count }, Sampling::Random { seed, .. }) => {
+                let mut rng = StdRng::from_seed(&[seed, len, blocks as usize, chunk as usize][..]);
+                for _ in 0..count {
+                    record(&random_configuration(&mut rng, len, blocks));
+                }
+            }
+            (Job::Random { .. }, Sampling::Exhaustive) => unreachable!(),
+        }
+    }
+}
+
+/// Most rows in a histogram. Longer ranges of lengths are bucketed.
+const HISTOGRAM_ROWS: u64 = 20;
+
+/// Widest histogram bar
+const HISTOGRAM_WIDTH: u64 = 40;
+
+/// One histogram of lengths and how often they came up, with a summary
+/// line first
+fn histogram(name: &str, counts: &BTreeMap<u64, u64>) -> String {
+    let (min, max) = match (counts.keys().next(), counts.keys().next_back()) {
+        (Some(&min), Some(&max)) => (min, max),
+        _ => return format!("  {}: none\n", name),
+    };
+    let total: u64 = counts.values().sum();
+    let sum: f64 = counts
+        .iter()
+        .map(|(&length, &count)| length as f64 * count as f64)
+        .sum();
+    let mut text = format!(
+        "  {}: min {}, mean {:.2}, max {}\n",
+        name,
+        min,
+        sum / total as f64,
+        max
+    );
+
+    let bucket = (max - min + 1).div_ceil(HISTOGRAM_ROWS);
+    let mut buckets = vec![0; ((max - min) / bucket + 1) as usize];
+    for (&length, &count) in counts {
+        buckets[((length - min) / bucket) as usize] += count;
+    }
+    let tallest = *buckets.iter().max().unwrap_or(&1);
+    let labels: Vec<String> = (0..buckets.len() as u64)
+        .map(|i| {
+            let low = min + i * bucket;
+            if bucket == 1 {
+                low.to_string()
+            } else {
+                format!("{}-{}", low, low + bucket - 1)
+            }
+        })
+        .collect();
+    let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    for (label, &count) in labels.iter().zip(buckets.iter()) {
+        let bar = (count * HISTOGRAM_WIDTH).div_ceil(tallest);
+        text += &format!(
+            "    {:>w$} {:<bw$} {}\n",
+            label,
+            "#".repeat(bar as usize),
+            count,
+            w = label_width,
+            bw = HISTOGRAM_WIDTH as usize
+        );
+    }
+    text
+}
+
+fn survey_histograms(results: &BTreeMap<(usize, u64), Distribution>) -> String {
+    let mut text = String::new();
+    for (&(banks, blocks), distribution) in results {
+        text += &format!(
+            "{} banks, {} blocks: {} configuration(s)\n",
+            banks, blocks, distribution.configurations
+        );
+        text += &histogram("transient", &distribution.transients);
+        text += &histogram("loop", &distribution.loops);
+    }
+    text
+}
+
+/// Same contents as survey_histograms(), as CSV with one row per length
+fn survey_csv(results: &BTreeMap<(usize, u64), Distribution>) -> String {
+    let mut csv = String::from("banks,blocks,measure,length,count\n");
+    for (&(banks, blocks), distribution) in results {
+        for &(measure, counts) in &[
+            ("transient", &distribution.transients),
+            ("loop", &distribution.loops),
+        ] {
+            for (length, count) in counts {
+                csv += &format!("{},{},{},{},{}\n", banks, blocks, measure, length, count);
+            }
+        }
+    }
+    csv
+}
+
+/// Parses a number or an inclusive range like "2-6"
+fn parse_range(s: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("Invalid range '{}'", s);
+    let (low, high) = match s.find('-') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, s),
+    };
+    let low = low.parse::<u64>().map_err(|_| invalid())?;
+    let high = high.parse::<u64>().map_err(|_| invalid())?;
+    if low > high {
+        return Err(invalid());
+    }
+    Ok((low, high))
+}
+
+struct SurveyOptions {
+    survey: Survey,
+    format: SurveyFormat,
+}
+
+/// Parses the command line after "survey". Err(None) means the usage
+/// should be printed.
+fn parse_survey_args(args: &[String]) -> Result<SurveyOptions, Option<String>> {
+    let mut options = SurveyOptions {
+        survey: Survey {
+            banks: (0, 0),
+            blocks: (0, 0),
+            sampling: Sampling::Exhaustive,
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            method: Method::HashSet,
+            policy: Policy::default(),
+        },
+        format: SurveyFormat::Histogram,
+    };
+    let (mut banks, mut blocks, mut samples, mut seed, mut exhaustive) =
+        (None, None, None, 0, false);
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(None);
+        let number = |value: &String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| Some(format!("Expected a number, got '{}'", value)))
+        };
+        let survey = &mut options.survey;
+        match arg.as_str() {
+            "--banks" => banks = Some(parse_range(value()?)?),
+            "--blocks" => blocks = Some(parse_range(value()?)?),
+            "--samples" => samples = Some(number(value()?)?),
+            "--seed" => seed = number(value()?)? as usize,
+            "--exhaustive" => exhaustive = true,
+            "--threads" => survey.threads = number(value()?)? as usize,
+            "--format" => options.format = SurveyFormat::from_str(value()?)?,
+            "--method" => survey.method = value()?.parse::<Method>()?,
+            "--tie-break" => survey.policy.tie_break = TieBreak::from_str(value()?)?,
+            "--direction" => survey.policy.direction = Direction::from_str(value()?)?,
+            "--start" => survey.policy.start = Start::from_str(value()?)?,
+            _ => return Err(None),
         }
     }
+
+    let banks = banks.ok_or(None)?;
+    if banks.0 == 0 {
+        return Err(Some(String::from("There must be at least one bank")));
+    }
+    options.survey.banks = (banks.0 as usize, banks.1 as usize);
+    options.survey.blocks = blocks.ok_or(None)?;
+    options.survey.sampling = match (samples, exhaustive) {
+        (Some(samples), false) => Sampling::Random { samples, seed },
+        (None, true) => Sampling::Exhaustive,
+        _ => return Err(None),
+    };
+    Ok(options)
 }
 
-fn cycle_count(mut banks: Vec<u32>) -> u32 {
-    let mut distributions = HashSet::new();
-    while distributions.insert(banks.clone()) {
-        redistribute(&mut banks);
+fn survey_main(args: &[String]) {
+    let options = match parse_survey_args(args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    match options.survey.run() {
+        Ok(results) => match options.format {
+            SurveyFormat::Histogram => print!("{}", survey_histograms(&results)),
+            SurveyFormat::Csv => print!("{}", survey_csv(&results)),
+        },
+        Err(message) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
     }
-    distributions.len() as u32
 }
 
 fn print_usage() {
     println!("Day 6: Memory Reallocation");
     println!("Usage:");
-    println!("06 <input>");
-    println!("  <input> - Memory banks (whitespace-separated)");
+    println!("06 [--method <method>] [--tie-break <rule>] [--direction <direction>]");
+    println!("   [--start <start>] [--trace <format>] [--predecessors [--depth <n>]]");
+    println!("   <input> / --file <file> / -");
+    println!("  --method    - Cycle detection: hash (default), brent or floyd");
+    println!("                (brent and floyd use constant memory)");
+    println!("  --tie-break - Bank emptied when several hold the most blocks:");
+    println!("                lowest (default), highest or random:<seed>");
+    println!("  --direction - Hand out blocks forward (default) or backward");
+    println!("  --start     - First bank to get a block: next (default), same");
+    println!("                (the emptied bank) or first (in the direction)");
+    println!("  --trace     - Print every state up to the first repeat, with the");
+    println!("                bank emptied and blocks moved (text or csv)");
+    println!("  --predecessors - Instead, list the states that redistribute into");
+    println!("                   the input, and theirs, down to --depth (default 1)");
+    println!("                   levels, and count the Garden-of-Eden states (with");
+    println!("                   no predecessors) found");
+    println!("  <input>     - Memory banks (whitespace-separated block counts)");
+    println!("  --file      - Read the banks from a file instead");
+    println!("  -           - Read the banks from stdin instead");
+    println!("Prints the number of redistributions until a state repeats, then");
+    println!("the size of the loop.");
+    println!("06 survey --banks <n> --blocks <n> (--samples <n> [--seed <n>] / --exhaustive)");
+    println!("   [--format <format>] [--threads <n>] [--method <method>] [policy flags]");
+    println!("  --banks, --blocks - Sizes to survey: a number or a range (e.g. 2-6)");
+    println!("  --samples <n>     - Random configurations per size");
+    println!("  --seed <n>        - Seed for the random configurations (default: 0)");
+    println!("  --exhaustive      - Every configuration of each size instead");
+    println!("  --format          - histogram (default) or csv of the transient and");
+    println!("                      loop lengths per size");
+    println!("  --threads <n>     - Threads to use (default: one per core)");
 }
 
-fn main() {
-    if let (2, Some(input)) = (args().len(), args().nth(1)) {
-        match input
-            .split_whitespace()
-            .map(|x| x.parse::<u32>())
-            .collect::<Result<Vec<u32>, _>>()
-        {
-            Ok(input) => println!("{}", cycle_count(input)),
-            Err(_) => {
-                println!("Error: Input must be unsigned integers.");
-                exit(-1);
+/// Where the banks are read from
+#[derive(Debug, PartialEq)]
+enum Source {
+    /// Banks given directly on the command line
+    Argv(String),
+    Stdin,
+    File(String),
+}
+
+impl Source {
+    fn read(&self) -> io::Result<String> {
+        match *self {
+            Source::Argv(ref input) => Ok(input.clone()),
+            Source::Stdin => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input)?;
+                Ok(input)
+            }
+            Source::File(ref filename) => {
+                let mut input = String::new();
+                File::open(filename)?.read_to_string(&mut input)?;
+                Ok(input)
             }
         }
-    } else {
-        print_usage();
-        exit(-1);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum InputError {
+    Empty,
+    /// A token that isn't a block count, by 1-based position
+    Invalid {
+        position: usize,
+        token: String,
+        too_large: bool,
+    },
+    /// The banks hold more than u64::MAX blocks between them, which
+    /// could overflow a single bank
+    TooManyBlocks,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputError::Empty => write!(f, "No memory banks given"),
+            InputError::Invalid {
+                position,
+                ref token,
+                too_large: true,
+            } => write!(
+                f,
+                "Bank {} ('{}') holds more than {} blocks",
+                position,
+                token,
+                u64::MAX
+            ),
+            InputError::Invalid {
+                position,
+                ref token,
+                too_large: false,
+            } => write!(
+                f,
+                "Bank {} ('{}') is not an unsigned integer",
+                position, token
+            ),
+            InputError::TooManyBlocks => {
+                write!(f, "The banks hold more than {} blocks in total", u64::MAX)
+            }
+        }
+    }
+}
+
+/// Parses whitespace-separated block counts
+fn parse_banks(input: &str) -> Result<Vec<u64>, InputError> {
+    let banks = input
+        .split_whitespace()
+        .enumerate()
+        .map(|(i, token)| {
+            token.parse::<u64>().map_err(|e| InputError::Invalid {
+                position: i + 1,
+                token: String::from(token),
+                too_large: *e.kind() == IntErrorKind::PosOverflow,
+            })
+        })
+        .collect::<Result<Vec<u64>, _>>()?;
+
+    if banks.is_empty() {
+        return Err(InputError::Empty);
+    }
+    // No bank can ever hold more than the total, so a total that fits
+    // means redistributing can't overflow
+    banks
+        .iter()
+        .try_fold(0u64, |total, &v| total.checked_add(v))
+        .ok_or(InputError::TooManyBlocks)?;
+    Ok(banks)
+}
+
+struct Options {
+    method: Method,
+    policy: Policy,
+    trace: Option<TraceFormat>,
+    predecessors: bool,
+    depth: Option<usize>,
+    source: Source,
+}
+
+/// Parses the command line. Err(None) means the usage should be printed.
+fn parse_args(args: &[String]) -> Result<Options, Option<String>> {
+    let mut options = Options {
+        method: Method::HashSet,
+        policy: Policy::default(),
+        trace: None,
+        predecessors: false,
+        depth: None,
+        source: Source::Stdin,
+    };
+    let mut source = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--method" => options.method = args.next().ok_or(None)?.parse::<Method>()?,
+            "--tie-break" => {
+                options.policy.tie_break = TieBreak::from_str(args.next().ok_or(None)?)?
+            }
+            "--direction" => {
+                options.policy.direction = Direction::from_str(args.next().ok_or(None)?)?
+            }
+            "--start" => options.policy.start = Start::from_str(args.next().ok_or(None)?)?,
+            "--trace" => options.trace = Some(TraceFormat::from_str(args.next().ok_or(None)?)?),
+            "--predecessors" => options.predecessors = true,
+            "--depth" => {
+                let depth = args.next().ok_or(None)?;
+                options.depth = Some(
+                    depth
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid depth '{}'", depth))?,
+                );
+            }
+            "--file" if source.is_none() => {
+                source = Some(Source::File(args.next().ok_or(None)?.clone()))
+            }
+            "-" if source.is_none() => source = Some(Source::Stdin),
+            _ if source.is_none() => source = Some(Source::Argv(arg.clone())),
+            _ => return Err(None),
+        }
+    }
+
+    if options.depth.is_some() && !options.predecessors {
+        return Err(Some(String::from(
+            "--depth can only be used with --predecessors",
+        )));
+    }
+    if options.predecessors && options.trace.is_some() {
+        return Err(Some(String::from(
+            "--predecessors can't be used with --trace",
+        )));
+    }
+
+    options.source = source.ok_or(None)?;
+    Ok(options)
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("survey") {
+        return survey_main(&args[1..]);
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(Some(message)) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+        Err(None) => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let input = match options.source.read() {
+        Ok(input) => input,
+        Err(e) => {
+            println!("IO Error: {}", e);
+            exit(-1);
+        }
+    };
+    let banks = match parse_banks(&input) {
+        Ok(banks) => banks,
+        Err(e) => {
+            println!("Error: {}", e);
+            exit(-1);
+        }
+    };
+
+    if options.predecessors {
+        let depth = options.depth.unwrap_or(1);
+        let tree = PredecessorTree::new(banks, &options.policy, depth);
+        print!("{}", tree.text());
+        println!(
+            "{} Garden-of-Eden state(s) within {} level(s)",
+            tree.gardens_of_eden().len(),
+            depth
+        );
+        return;
+    }
+
+    match options.trace {
+        Some(TraceFormat::Text) => print!("{}", Trace::new(banks, &options.policy).text()),
+        Some(TraceFormat::Csv) => print!("{}", Trace::new(banks, &options.policy).csv()),
+        None => {
+            let (cycles, loop_size) = cycle_count(banks, options.method, &options.policy);
+            println!("{}", cycles);
+            println!("{}", loop_size);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{env, fs};
+    use test::Bencher;
+
+    fn random_banks(len: usize, max: u32, seed: usize) -> Vec<u64> {
+        let mut rng = StdRng::from_seed(&[seed][..]);
+        (0..len)
+            .map(|_| u64::from(rng.gen_range(0, max + 1)))
+            .collect()
+    }
 
     #[test]
     fn aoc_example_max() {
-        assert_eq!(max_bank(&vec![1, 3, 3, 2]), (1, 3));
+        assert_eq!(max_bank(&[1, 3, 3, 2], TieBreak::Lowest), (1, 3));
+        assert_eq!(max_bank(&[1, 3, 3, 2], TieBreak::Highest), (2, 3));
     }
 
     #[test]
     fn aoc_example_redistributions() {
         let mut input = vec![0, 2, 7, 0];
 
-        redistribute(&mut input);
+        redistribute(&mut input, &Policy::default());
         assert_eq!(input, vec![2, 4, 1, 2]);
 
-        redistribute(&mut input);
+        redistribute(&mut input, &Policy::default());
         assert_eq!(input, vec![3, 1, 2, 3]);
 
-        redistribute(&mut input);
+        redistribute(&mut input, &Policy::default());
         assert_eq!(input, vec![0, 2, 3, 4]);
 
-        redistribute(&mut input);
+        redistribute(&mut input, &Policy::default());
         assert_eq!(input, vec![1, 3, 4, 1]);
 
-        redistribute(&mut input);
+        redistribute(&mut input, &Policy::default());
         assert_eq!(input, vec![2, 4, 1, 2]);
     }
 
+    fn redistributed(mut banks: Vec<u64>, policy: Policy) -> Vec<u64> {
+        redistribute(&mut banks, &policy);
+        banks
+    }
+
+    #[test]
+    fn tie_breaks() {
+        let highest = Policy {
+            tie_break: TieBreak::Highest,
+            ..Policy::default()
+        };
+        assert_eq!(
+            redistributed(vec![2, 0, 2], Policy::default()),
+            vec![0, 1, 3]
+        );
+        assert_eq!(redistributed(vec![2, 0, 2], highest), vec![3, 1, 0]);
+
+        // Random picks one of the tied banks, always the same one for a
+        // given seed and state, and both across seeds
+        let mut seen = Vec::new();
+        for seed in 0..20 {
+            let policy = Policy {
+                tie_break: TieBreak::Random(seed),
+                ..Policy::default()
+            };
+            let banks = redistributed(vec![2, 0, 2], policy);
+            assert!(banks == vec![0, 1, 3] || banks == vec![3, 1, 0]);
+            assert_eq!(redistributed(vec![2, 0, 2], policy), banks);
+            if !seen.contains(&banks) {
+                seen.push(banks);
+            }
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn directions_and_starts() {
+        let policy = |direction, start| Policy {
+            tie_break: TieBreak::Lowest,
+            direction,
+            start,
+        };
+        let banks = vec![0, 5, 0, 0];
+        let expected = [
+            (Direction::Forward, Start::Next, vec![1, 1, 2, 1]),
+            (Direction::Forward, Start::Same, vec![1, 2, 1, 1]),
+            (Direction::Forward, Start::First, vec![2, 1, 1, 1]),
+            (Direction::Backward, Start::Next, vec![2, 1, 1, 1]),
+            (Direction::Backward, Start::Same, vec![1, 2, 1, 1]),
+            (Direction::Backward, Start::First, vec![1, 1, 1, 2]),
+        ];
+        for &(direction, start, ref after) in &expected {
+            assert_eq!(
+                &redistributed(banks.clone(), policy(direction, start)),
+                after
+            );
+        }
+
+        // Leftover blocks wrap around in both directions
+        assert_eq!(
+            redistributed(vec![1, 0, 0, 3], policy(Direction::Forward, Start::Next)),
+            vec![2, 1, 1, 0]
+        );
+        assert_eq!(
+            redistributed(vec![3, 0, 0, 1], policy(Direction::Backward, Start::Next)),
+            vec![0, 1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn policies_in_cycle_count() {
+        let policies = [
+            Policy::default(),
+            Policy {
+                tie_break: TieBreak::Random(7),
+                direction: Direction::Backward,
+                start: Start::Same,
+            },
+            Policy {
+                tie_break: TieBreak::Highest,
+                direction: Direction::Forward,
+                start: Start::First,
+            },
+        ];
+        for policy in &policies {
+            for seed in 0..20 {
+                let banks = random_banks(2 + seed % 10, 12, seed);
+                let expected = cycle_count(banks.clone(), Method::HashSet, policy);
+                assert_eq!(cycle_count(banks.clone(), Method::Brent, policy), expected);
+                assert_eq!(cycle_count(banks, Method::Floyd, policy), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn policy_args() {
+        let args: Vec<String> = [
+            "--tie-break",
+            "random:42",
+            "--direction",
+            "backward",
+            "--start",
+            "first",
+            "0 2 7 0",
+        ]
+        .iter()
+        .map(|&arg| String::from(arg))
+        .collect();
+        let options = parse_args(&args).unwrap();
+        assert_eq!(
+            options.policy,
+            Policy {
+                tie_break: TieBreak::Random(42),
+                direction: Direction::Backward,
+                start: Start::First,
+            }
+        );
+        assert_eq!(options.source, Source::Argv(String::from("0 2 7 0")));
+
+        assert_eq!(
+            TieBreak::from_str("random:x"),
+            Err(String::from("Invalid random seed in 'random:x'"))
+        );
+    }
+
+    #[test]
+    fn aoc_example_trace() {
+        let trace = Trace::new(vec![0, 2, 7, 0], &Policy::default());
+        assert_eq!(
+            trace,
+            Trace {
+                states: vec![
+                    vec![0, 2, 7, 0],
+                    vec![2, 4, 1, 2],
+                    vec![3, 1, 2, 3],
+                    vec![0, 2, 3, 4],
+                    vec![1, 3, 4, 1],
+                    vec![2, 4, 1, 2],
+                ],
+                moves: vec![(2, 7), (1, 4), (0, 3), (3, 4), (2, 4)],
+                loop_start: 1,
+            }
+        );
+
+        assert_eq!(
+            trace.text(),
+            "step  banks    bank  blocks
+   0  0 2 7 0     2       7
+   1  2 4 1 2     1       4  loop start
+   2  3 1 2 3     0       3
+   3  0 2 3 4     3       4
+   4  1 3 4 1     2       4
+   5  2 4 1 2                repeats 1
+"
+        );
+        assert_eq!(
+            trace.csv(),
+            "step,bank,blocks,mark,b0,b1,b2,b3
+0,2,7,,0,2,7,0
+1,1,4,loop start,2,4,1,2
+2,0,3,,3,1,2,3
+3,3,4,,0,2,3,4
+4,2,4,,1,3,4,1
+5,,,repeats 1,2,4,1,2
+"
+        );
+    }
+
+    #[test]
+    fn immediate_loop_trace() {
+        let trace = Trace::new(vec![0, 0], &Policy::default());
+        assert_eq!(trace.moves, vec![(0, 0)]);
+        assert_eq!(trace.loop_start, 0);
+        assert!(trace.text().ends_with(
+            "   0  0 0     0       0  loop start
+   1  0 0                repeats 0
+"
+        ));
+    }
+
+    #[test]
+    fn bank_input() {
+        assert_eq!(parse_banks("0\t2  7\n0\n"), Ok(vec![0, 2, 7, 0]));
+        assert_eq!(parse_banks(""), Err(InputError::Empty));
+        assert_eq!(parse_banks(" \n "), Err(InputError::Empty));
+
+        let invalid = parse_banks("1 -2 3").unwrap_err();
+        assert_eq!(
+            invalid,
+            InputError::Invalid {
+                position: 2,
+                token: String::from("-2"),
+                too_large: false,
+            }
+        );
+        assert_eq!(
+            invalid.to_string(),
+            "Bank 2 ('-2') is not an unsigned integer"
+        );
+
+        let too_large = parse_banks("1 2 18446744073709551616").unwrap_err();
+        assert_eq!(
+            too_large.to_string(),
+            "Bank 3 ('18446744073709551616') holds more than 18446744073709551615 blocks"
+        );
+
+        assert_eq!(
+            parse_banks("18446744073709551615 1"),
+            Err(InputError::TooManyBlocks)
+        );
+    }
+
+    #[test]
+    fn wide_banks() {
+        let third = u64::MAX / 3;
+        assert_eq!(
+            redistributed(vec![u64::MAX - 2, 1, 1], Policy::default()),
+            vec![third - 1, third + 1, third]
+        );
+        assert_eq!(
+            cycle_count(
+                parse_banks("18446744073709551613 1 1").unwrap(),
+                Method::Brent,
+                &Policy::default()
+            ),
+            cycle_count(
+                vec![u64::MAX - 2, 1, 1],
+                Method::HashSet,
+                &Policy::default()
+            )
+        );
+    }
+
+    #[test]
+    fn file_source() {
+        let path = env::temp_dir().join("advent-2017-06-banks.txt");
+        fs::write(&path, "0 2 7 0\n").unwrap();
+        let source = Source::File(path.to_string_lossy().into_owned());
+        assert_eq!(parse_banks(&source.read().unwrap()), Ok(vec![0, 2, 7, 0]));
+        fs::remove_file(&path).unwrap();
+
+        assert!(Source::File(String::from("/nonexistent/banks"))
+            .read()
+            .is_err());
+        assert_eq!(
+            parse_args(&[String::from("--file"), String::from("banks.txt")])
+                .unwrap()
+                .source,
+            Source::File(String::from("banks.txt"))
+        );
+        assert_eq!(
+            parse_args(&[String::from("-")]).unwrap().source,
+            Source::Stdin
+        );
+    }
+
+    #[test]
+    fn aoc_example_predecessors() {
+        let policy = Policy::default();
+        assert_eq!(
+            predecessors(&[2, 4, 1, 2], &policy),
+            vec![
+                vec![0, 2, 7, 0],
+                vec![0, 3, 6, 0],
+                vec![1, 3, 4, 1],
+                vec![1, 3, 5, 0],
+            ]
+        );
+        assert!(predecessors(&[0, 2, 7, 0], &policy).is_empty());
+        assert_eq!(predecessors(&[0, 0], &policy), vec![vec![0, 0]]);
+    }
+
+    #[test]
+    fn predecessors_match_brute_force() {
+        // Every state of 3 banks holding at most 5 blocks, and where each
+        // one goes
+        let mut states = Vec::new();
+        for a in 0..6 {
+            for b in 0..6 - a {
+                for c in 0..6 - a - b {
+                    states.push(vec![a, b, c]);
+                }
+            }
+        }
+
+        let policies = [
+            Policy::default(),
+            Policy {
+                tie_break: TieBreak::Highest,
+                direction: Direction::Backward,
+                start: Start::Same,
+            },
+            Policy {
+                tie_break: TieBreak::Random(3),
+                direction: Direction::Forward,
+                start: Start::First,
+            },
+        ];
+        for policy in &policies {
+            for target in &states {
+                let expected: Vec<Vec<u64>> = states
+                    .iter()
+                    .filter(|&banks| &redistributed(banks.clone(), *policy) == target)
+                    .cloned()
+                    .collect();
+                assert_eq!(predecessors(target, policy), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn predecessor_tree() {
+        let tree = PredecessorTree::new(vec![3, 1, 2, 3], &Policy::default(), 2);
+        assert_eq!(
+            tree.gardens_of_eden(),
+            vec![&[1, 7, 0, 1][..], &[2, 5, 0, 2][..], &[2, 6, 0, 1][..]]
+        );
+        assert_eq!(
+            tree.text(),
+            "3 1 2 3
+  1 7 0 1  (no predecessors)
+  2 4 1 2
+    0 2 7 0
+    0 3 6 0
+    1 3 4 1
+    1 3 5 0
+  2 5 0 2  (no predecessors)
+  2 6 0 1  (no predecessors)
+"
+        );
+
+        // Nothing is known past the depth limit
+        let tree = PredecessorTree::new(vec![0, 2, 7, 0], &Policy::default(), 0);
+        assert_eq!(tree.predecessors, None);
+        assert!(tree.gardens_of_eden().is_empty());
+    }
+
+    fn survey(
+        banks: (usize, usize),
+        blocks: (u64, u64),
+        sampling: Sampling,
+        threads: usize,
+    ) -> Survey {
+        Survey {
+            banks,
+            blocks,
+            sampling,
+            threads,
+            method: Method::Brent,
+            policy: Policy::default(),
+        }
+    }
+
+    #[test]
+    fn configuration_counts() {
+        assert_eq!(configurations(1, 7), Some(1));
+        assert_eq!(configurations(2, 2), Some(3));
+        assert_eq!(configurations(4, 30), Some(5456));
+        assert_eq!(configurations(16, 100), Some(2_396_826_047_070_372_396));
+        assert_eq!(configurations(100, 1000), None);
+        assert_eq!(configurations(3, u64::MAX), None);
+
+        let mut seen = Vec::new();
+        for_each_configuration(&mut vec![0; 3], 0, 2, &mut |banks| {
+            seen.push(banks.to_vec())
+        });
+        assert_eq!(
+            seen,
+            vec![
+                vec![0, 0, 2],
+                vec![0, 1, 1],
+                vec![0, 2, 0],
+                vec![1, 0, 1],
+                vec![1, 1, 0],
+                vec![2, 0, 0],
+            ]
+        );
+
+        let mut rng = StdRng::from_seed(&[1][..]);
+        for _ in 0..100 {
+            let banks = random_configuration(&mut rng, 5, 20);
+            assert_eq!(banks.len(), 5);
+            assert_eq!(banks.iter().sum::<u64>(), 20);
+        }
+
+        // Every configuration is about as likely, including ones with an
+        // empty bank between two others
+        let mut counts = BTreeMap::new();
+        for _ in 0..60_000 {
+            *counts
+                .entry(random_configuration(&mut rng, 3, 2))
+                .or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 6);
+        for &count in counts.values() {
+            assert!(count > 9_000 && count < 11_000, "{:?}", counts);
+        }
+        assert_eq!(random_configuration(&mut rng, 1, 7), vec![7]);
+        assert_eq!(random_configuration(&mut rng, 4, 0), vec![0; 4]);
+    }
+
+    #[test]
+    fn exhaustive_survey() {
+        // [0, 2] and [1, 1] loop into each other, and [2, 0] leads into
+        // that loop
+        let results = survey((2, 2), (2, 2), Sampling::Exhaustive, 1)
+            .run()
+            .unwrap();
+        let mut expected = Distribution::default();
+        expected.record(2, 2);
+        expected.record(2, 2);
+        expected.record(3, 2);
+        assert_eq!(results.get(&(2, 2)), Some(&expected));
+        assert_eq!(
+            survey_csv(&results),
+            "banks,blocks,measure,length,count
+2,2,transient,0,2
+2,2,transient,1,1
+2,2,loop,2,3
+"
+        );
+
+        // A single bank always loops on itself
+        let results = survey((1, 1), (0, 3), Sampling::Exhaustive, 1)
+            .run()
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results.values().all(|d| d.configurations == 1));
+
+        assert!(survey((16, 16), (100, 100), Sampling::Exhaustive, 1)
+            .run()
+            .is_err());
+    }
+
+    #[test]
+    fn survey_threads_agree() {
+        let random = Sampling::Random {
+            samples: 1000,
+            seed: 7,
+        };
+        for &sampling in &[Sampling::Exhaustive, random] {
+            let expected = survey((2, 4), (0, 12), sampling, 1).run().unwrap();
+            assert_eq!(
+                survey((2, 4), (0, 12), sampling, 4).run().unwrap(),
+                expected
+            );
+            for (&(banks, blocks), distribution) in &expected {
+                let count = match sampling {
+                    Sampling::Exhaustive => configurations(banks, blocks).unwrap(),
+                    Sampling::Random { samples, .. } => samples,
+                };
+                assert_eq!(distribution.configurations, count);
+                assert_eq!(distribution.loops.values().sum::<u64>(), count);
+            }
+        }
+    }
+
+    #[test]
+    fn survey_histogram() {
+        let results = survey((2, 2), (2, 2), Sampling::Exhaustive, 1)
+            .run()
+            .unwrap();
+        let text = survey_histograms(&results);
+        assert!(text.starts_with("2 banks, 2 blocks: 3 configuration(s)\n"));
+        assert!(text.contains("  transient: min 0, mean 0.33, max 1\n"));
+        assert!(text.contains(&format!("    1 {:<40} 1\n", "#".repeat(20))));
+
+        // Wide ranges of lengths are bucketed
+        let counts: BTreeMap<u64, u64> = (0..100).map(|length| (length, 1)).collect();
+        let text = histogram("loop", &counts);
+        assert_eq!(text.lines().count(), 21);
+        assert!(text.contains("    95-99 "));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("4"), Ok((4, 4)));
+        assert_eq!(parse_range("2-6"), Ok((2, 6)));
+        assert!(parse_range("6-2").is_err());
+        assert!(parse_range("2-").is_err());
+        assert!(parse_range("x").is_err());
+    }
+
     #[test]
     fn aoc_example_cycle_count() {
-        assert_eq!(cycle_count(vec![0, 2, 7, 0]), 5);
+        assert_eq!(
+            cycle_count(vec![0, 2, 7, 0], Method::HashSet, &Policy::default()).0,
+            5
+        );
+    }
+
+    #[test]
+    fn aoc_example_loop_size() {
+        assert_eq!(
+            cycle_count(vec![0, 2, 7, 0], Method::HashSet, &Policy::default()),
+            (5, 4)
+        );
+    }
+
+    #[test]
+    fn immediate_loop() {
+        // [0, 0] redistributes into itself
+        assert_eq!(
+            cycle_count(vec![0, 0], Method::HashSet, &Policy::default()),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn aoc_example_constant_memory() {
+        for &method in &[Method::Brent, Method::Floyd] {
+            assert_eq!(
+                cycle_count(vec![0, 2, 7, 0], method, &Policy::default()),
+                (5, 4)
+            );
+            assert_eq!(cycle_count(vec![0, 0], method, &Policy::default()), (1, 1));
+        }
+    }
+
+    #[test]
+    fn methods_match_hash_map() {
+        for seed in 0..50 {
+            let banks = random_banks(2 + seed % 14, 20, seed);
+            let expected = cycle_count(banks.clone(), Method::HashSet, &Policy::default());
+            assert_eq!(
+                cycle_count(banks.clone(), Method::Brent, &Policy::default()),
+                expected
+            );
+            assert_eq!(
+                cycle_count(banks, Method::Floyd, &Policy::default()),
+                expected
+            );
+        }
+    }
+
+    #[bench]
+    fn bench_hash_map_16(b: &mut Bencher) {
+        let banks = random_banks(16, 16, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::HashSet, &Policy::default()));
+    }
+
+    #[bench]
+    fn bench_brent_16(b: &mut Bencher) {
+        let banks = random_banks(16, 16, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::Brent, &Policy::default()));
+    }
+
+    #[bench]
+    fn bench_floyd_16(b: &mut Bencher) {
+        let banks = random_banks(16, 16, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::Floyd, &Policy::default()));
+    }
+
+    #[bench]
+    fn bench_hash_map_200(b: &mut Bencher) {
+        let banks = random_banks(200, 1000, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::HashSet, &Policy::default()));
+    }
+
+    #[bench]
+    fn bench_brent_200(b: &mut Bencher) {
+        let banks = random_banks(200, 1000, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::Brent, &Policy::default()));
+    }
+
+    #[bench]
+    fn bench_floyd_200(b: &mut Bencher) {
+        let banks = random_banks(200, 1000, 1);
+        b.iter(|| cycle_count(banks.clone(), Method::Floyd, &Policy::default()));
     }
 }
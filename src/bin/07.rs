@@ -1,11 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::env::args;
+use std::fmt;
 use std::process::exit;
 
-// Each node in the tree can be represented by its string value
-// and the string values of its children
+// Each node in the tree can be represented by its string value, its
+// weight and the string values of its children
 struct Node<'a> {
     value: &'a str,
+    weight: u64,
     child_values: Vec<&'a str>,
 }
 
@@ -14,10 +17,23 @@ impl<'a> Node<'a> {
     //   $value ($weight) -> $child_0, $child_1, ..., $child_n
     // The '-> ...' sequence is omitted (including ->!) if the
     // node has no children.
-    fn from_str(line: &'a str) -> Node<'a> {
-        Node {
-            // Every valid node has a value (i.e. no error-check here)
-            value: line.split_whitespace().next().unwrap(),
+    fn from_str(line: &'a str) -> Result<Node<'a>, String> {
+        let mut words = line.split_whitespace();
+        let value = words
+            .next()
+            .ok_or_else(|| String::from("Missing program name"))?;
+        let weight = match words.next() {
+            Some(weight) if weight.starts_with('(') && weight.ends_with(')') => weight
+                [1..weight.len() - 1]
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid weight '{}'", weight))?,
+            Some(weight) => return Err(format!("Invalid weight '{}'", weight)),
+            None => return Err(format!("Missing weight for '{}'", value)),
+        };
+
+        Ok(Node {
+            value,
+            weight,
 
             // Split the line on the '>' from '->'. If this results in
             //   1. If this results in two sections, split the second
@@ -25,66 +41,647 @@ impl<'a> Node<'a> {
             //   2. If this doesn't result in two sections, there are no
             //      children
             child_values: {
-                match line.split(|c| c == '>').skip(1).next() {
-                    Some(child_values) => {
-                        child_values.split(|c| c == ',').map(|s| s.trim()).collect()
-                    }
+                match line.split('>').nth(1) {
+                    Some(child_values) => child_values.split(',').map(|s| s.trim()).collect(),
                     None => vec![],
                 }
             },
+        })
+    }
+}
+
+/// Parses every line of a tree, with its line number, reporting the
+/// first bad line. Blank lines are skipped.
+fn parse_tree<'a>(tree: &'a str) -> Result<Vec<(usize, Node<'a>)>, String> {
+    tree.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            Node::from_str(line)
+                .map(|node| (i + 1, node))
+                .map_err(|e| format!("Line {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+/// A structural problem with the tower. Lines are numbered from 1.
+#[derive(Debug, PartialEq)]
+enum TreeError {
+    Empty,
+    /// A program is held up but never defined
+    Undefined {
+        program: String,
+        parent: String,
+        line: usize,
+    },
+    /// A program is defined on more than one line
+    Duplicate {
+        program: String,
+        first_line: usize,
+        line: usize,
+    },
+    /// A program is held up by more than one program
+    MultipleParents {
+        program: String,
+        first_line: usize,
+        line: usize,
+    },
+    /// More than one program isn't held up by anything
+    MultipleRoots {
+        roots: Vec<(String, usize)>,
+    },
+    /// Programs that (indirectly) hold themselves up
+    Cycle {
+        programs: Vec<(String, usize)>,
+    },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // "a (line 1), b (line 4)"
+        let list = |programs: &[(String, usize)]| {
+            programs
+                .iter()
+                .map(|&(ref program, line)| format!("{} (line {})", program, line))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match *self {
+            TreeError::Empty => write!(f, "The tower is empty"),
+            TreeError::Undefined {
+                ref program,
+                ref parent,
+                line,
+            } => write!(
+                f,
+                "Line {}: {} holds up {}, which is never defined",
+                line, parent, program
+            ),
+            TreeError::Duplicate {
+                ref program,
+                first_line,
+                line,
+            } => write!(
+                f,
+                "Line {}: {} is already defined on line {}",
+                line, program, first_line
+            ),
+            TreeError::MultipleParents {
+                ref program,
+                first_line,
+                line,
+            } => write!(
+                f,
+                "Line {}: {} is already held up on line {}",
+                line, program, first_line
+            ),
+            TreeError::MultipleRoots { ref roots } => {
+                write!(f, "More than one program is at the bottom: {}", list(roots))
+            }
+            TreeError::Cycle { ref programs } => {
+                write!(f, "Programs hold themselves up: {}", list(programs))
+            }
+        }
+    }
+}
+
+/// Checks that the nodes form a single tree, returning every problem found
+fn validate(nodes: &[(usize, Node)]) -> Result<(), Vec<TreeError>> {
+    if nodes.is_empty() {
+        return Err(vec![TreeError::Empty]);
+    }
+    let mut errors = Vec::new();
+
+    // Line each program is defined on, and the first program (and line)
+    // holding it up
+    let mut defined: HashMap<&str, usize> = HashMap::new();
+    let mut parents: HashMap<&str, (&str, usize)> = HashMap::new();
+    for &(line, ref node) in nodes {
+        match defined.get(node.value) {
+            Some(&first_line) => errors.push(TreeError::Duplicate {
+                program: String::from(node.value),
+                first_line,
+                line,
+            }),
+            None => {
+                defined.insert(node.value, line);
+            }
+        }
+        for &child in &node.child_values {
+            match parents.get(child) {
+                Some(&(_, first_line)) => errors.push(TreeError::MultipleParents {
+                    program: String::from(child),
+                    first_line,
+                    line,
+                }),
+                None => {
+                    parents.insert(child, (node.value, line));
+                }
+            }
+        }
+    }
+
+    for &(line, ref node) in nodes {
+        for &child in &node.child_values {
+            if !defined.contains_key(child) {
+                errors.push(TreeError::Undefined {
+                    program: String::from(child),
+                    parent: String::from(node.value),
+                    line,
+                });
+            }
+        }
+    }
+
+    // Duplicates are only counted once, at their first definition
+    let roots: Vec<(String, usize)> = nodes
+        .iter()
+        .filter(|&&(line, ref node)| {
+            !parents.contains_key(node.value) && defined[node.value] == line
+        })
+        .map(|&(line, ref node)| (String::from(node.value), line))
+        .collect();
+    if roots.len() > 1 {
+        errors.push(TreeError::MultipleRoots { roots });
+    }
+
+    // Depth-first search over the children of every program. A child
+    // that's still on the search path closes a cycle. Only the first
+    // definition of duplicates counts.
+    let mut children: HashMap<&str, &[&str]> = HashMap::new();
+    for &(line, ref node) in nodes {
+        if defined[node.value] == line {
+            children.insert(node.value, &node.child_values);
+        }
+    }
+    // false while a program is on the search path, true once it's done
+    let mut done: HashMap<&str, bool> = HashMap::new();
+    for (_, node) in nodes {
+        if done.contains_key(node.value) {
+            continue;
+        }
+        let mut path = vec![(node.value, 0)];
+        done.insert(node.value, false);
+        while let Some(&mut (program, ref mut next_child)) = path.last_mut() {
+            let child = children.get(program).and_then(|c| c.get(*next_child));
+            *next_child += 1;
+            match child {
+                Some(&child) => match done.get(child) {
+                    Some(&false) => {
+                        let start = path.iter().position(|&(p, _)| p == child).unwrap();
+                        errors.push(TreeError::Cycle {
+                            programs: path[start..]
+                                .iter()
+                                .map(|&(p, _)| (String::from(p), defined[p]))
+                                .collect(),
+                        });
+                    }
+                    Some(&true) => (),
+                    None => {
+                        done.insert(child, false);
+                        path.push((child, 0));
+                    }
+                },
+                None => {
+                    done.insert(program, true);
+                    path.pop();
+                }
+            }
         }
     }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
-/// Combines the value and child values of a node into a single vector.
-/// For this AoC solution, there's no need to distinguish between the
-/// value and child values.
-fn flatten<'a>(mut node: Node<'a>) -> Vec<&'a str> {
-    node.child_values.push(node.value);
-    node.child_values
+/// Index of a program in a Tree
+type Id = usize;
+
+/// A program whose weight has to change to balance the tower
+#[derive(Debug, PartialEq)]
+struct Fix {
+    program: Id,
+    weight: u64,
+    correct: u64,
 }
 
-/// Get the root node from a tree (see AoC problem for tree format)
-fn root_node(tree: String) -> String {
-    let mut tree_nodes = HashSet::with_capacity(tree.lines().count());
-
-    // Every node in the tree appears exactly twice (once as child + once
-    // as parent) EXCEPT for the root node (only once as parent). Traverse
-    // the tree, inserting all new nodes encountered (including children) and
-    // deleting existing nodes when a duplicate is encountered. Since every
-    // node except the root node has exactly one duplicate, the resulting
-    // HashSet should ONLY contain the root node
-    for line in tree.lines() {
-        let line_nodes = flatten(Node::from_str(line));
-
-        for node in line_nodes {
-            if tree_nodes.contains(node) {
-                tree_nodes.remove(node);
-            } else {
-                tree_nodes.insert(node);
+/// The whole tower of programs. Names are interned, so programs refer to
+/// each other by Id.
+#[derive(Clone, Debug, Default)]
+struct Tree {
+    names: Vec<String>,
+    ids: HashMap<String, Id>,
+    weights: Vec<u64>,
+    parents: Vec<Option<Id>>,
+    children: Vec<Vec<Id>>,
+    /// Weight of each program plus everything it holds up. These are u128
+    /// so the totals can't overflow, even if single weights are near
+    /// u64::MAX.
+    subtree_weights: Vec<u128>,
+}
+
+impl Tree {
+    fn new(nodes: &[(usize, Node)]) -> Tree {
+        let mut tree = Tree::default();
+        for (_, node) in nodes {
+            let id = tree.intern(node.value);
+            tree.weights[id] = node.weight;
+            for child in &node.child_values {
+                let child = tree.intern(child);
+                tree.children[id].push(child);
+                tree.parents[child] = Some(id);
+            }
+        }
+        tree.update_subtree_weights();
+        tree
+    }
+
+    /// Id of a program, adding it if it hasn't been seen yet
+    fn intern(&mut self, name: &str) -> Id {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(String::from(name));
+        self.ids.insert(String::from(name), id);
+        self.weights.push(0);
+        self.parents.push(None);
+        self.children.push(Vec::new());
+        self.subtree_weights.push(0);
+        id
+    }
+
+    fn update_subtree_weights(&mut self) {
+        // Children always come before their parents in the post-order, so
+        // each child's total is complete when it's added to its parent
+        self.subtree_weights = self.weights.iter().map(|&w| u128::from(w)).collect();
+        for id in self.post_order() {
+            if let Some(parent) = self.parents[id] {
+                self.subtree_weights[parent] += self.subtree_weights[id];
             }
         }
     }
-    String::from(*tree_nodes.iter().next().unwrap())
+
+    /// Every program held up (directly or not) by a program without a
+    /// parent, children first
+    fn post_order(&self) -> Vec<Id> {
+        let mut order = Vec::with_capacity(self.names.len());
+        for root in (0..self.names.len()).filter(|&id| self.parents[id].is_none()) {
+            let mut stack = vec![(root, 0)];
+            while let Some((id, next_child)) = stack.pop() {
+                match self.children[id].get(next_child) {
+                    Some(&child) => {
+                        stack.push((id, next_child + 1));
+                        stack.push((child, 0));
+                    }
+                    None => order.push(id),
+                }
+            }
+        }
+        order
+    }
+
+    fn id(&self, name: &str) -> Option<Id> {
+        self.ids.get(name).cloned()
+    }
+
+    fn name(&self, id: Id) -> &str {
+        &self.names[id]
+    }
+
+    /// The bottom program, i.e. the first one without a parent
+    fn root(&self) -> Option<Id> {
+        (0..self.names.len()).find(|&id| self.parents[id].is_none())
+    }
+
+    /// The program, its parent, and so on down to the root
+    fn path_to_root(&self, id: Id) -> Vec<Id> {
+        let mut path = vec![id];
+        while let Some(parent) = self.parents[path[path.len() - 1]] {
+            path.push(parent);
+        }
+        path
+    }
+
+    /// Number of programs between a program and the root (0 for the root)
+    fn depth(&self, id: Id) -> usize {
+        self.path_to_root(id).len() - 1
+    }
+
+    /// The highest program that holds up both programs (either of them can
+    /// be it)
+    fn lowest_common_ancestor(&self, a: Id, b: Id) -> Option<Id> {
+        let path: HashSet<Id> = self.path_to_root(a).into_iter().collect();
+        self.path_to_root(b)
+            .into_iter()
+            .find(|id| path.contains(id))
+    }
+
+    /// The program and everything it holds up, each with its depth below
+    /// the program, parents before children
+    fn subtree(&self, id: Id) -> Vec<(Id, usize)> {
+        let mut subtree = Vec::new();
+        let mut stack = vec![(id, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            subtree.push((id, depth));
+            for &child in self.children[id].iter().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+        subtree
+    }
+
+    /// One program per line, indented by depth, with its weight and the
+    /// weight of everything it holds up
+    fn subtree_text(&self, id: Id) -> String {
+        self.subtree(id)
+            .into_iter()
+            .map(|(id, depth)| {
+                format!(
+                    "{}{} ({}), total {}\n",
+                    "  ".repeat(depth),
+                    self.name(id),
+                    self.weights[id],
+                    self.subtree_weights[id]
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the children of a program don't all weigh the same
+    fn unbalanced(&self, id: Id) -> bool {
+        let children = &self.children[id];
+        children
+            .iter()
+            .any(|&child| self.subtree_weights[child] != self.subtree_weights[children[0]])
+    }
+
+    /// If the children of a program don't all weigh the same, the children
+    /// that could be the odd one out, each with the total weight it should
+    /// have. With only two children, both are candidates.
+    fn odd_children(&self, id: Id) -> Result<Vec<(Id, u128)>, String> {
+        let children = &self.children[id];
+        let total = |child: Id| self.subtree_weights[child];
+        let with_total = |t: u128| *children.iter().find(|&&child| total(child) == t).unwrap();
+
+        // Distinct total weights of the children, with how many have each
+        let mut counts: Vec<(u128, usize)> = Vec::new();
+        for &child in children {
+            match counts.iter_mut().find(|&&mut (t, _)| t == total(child)) {
+                Some(&mut (_, ref mut count)) => *count += 1,
+                None => counts.push((total(child), 1)),
+            }
+        }
+
+        match counts.as_slice() {
+            [] | [_] => Ok(vec![]),
+            &[(a, 1), (b, count)] if count > 1 => Ok(vec![(with_total(a), b)]),
+            &[(a, count), (b, 1)] if count > 1 => Ok(vec![(with_total(b), a)]),
+            &[(a, 1), (b, 1)] => Ok(vec![(with_total(a), b), (with_total(b), a)]),
+            &[(a, x), (b, y)] if x > 1 && y > 1 => Err(format!(
+                "{} holds up {} programs weighing {} and {} weighing {}, so \
+                 there's no single odd program",
+                self.name(id),
+                x,
+                a,
+                y,
+                b
+            )),
+            _ => Err(self.more_than_one(id)),
+        }
+    }
+
+    fn more_than_one(&self, id: Id) -> String {
+        format!(
+            "More than one weight is wrong: the programs held up by {} have more \
+             than two different weights",
+            self.name(id)
+        )
+    }
+
+    /// Finds the single program whose weight has to change so that, for
+    /// every program, everything it holds up weighs the same on each side.
+    /// Ok(None) means the tower is already balanced.
+    fn fix(&self) -> Result<Option<Fix>, String> {
+        // Changing one weight unbalances (at most) the programs below it, so
+        // the deepest unbalanced program holds up the one to change. Its
+        // other children, and everything they hold up, are balanced.
+        let mut deepest: Option<(usize, Id)> = None;
+        for id in (0..self.names.len()).filter(|&id| self.unbalanced(id)) {
+            let depth = self.depth(id);
+            if deepest.is_none_or(|(d, _)| depth > d) {
+                deepest = Some((depth, id));
+            }
+        }
+        let parent = match deepest {
+            Some((_, parent)) => parent,
+            None => return Ok(None),
+        };
+
+        let candidates = self.odd_children(parent)?;
+        let fixes: Vec<Result<Fix, String>> = candidates
+            .iter()
+            .map(|&(program, total)| self.fix_total(program, total))
+            .collect();
+        match fixes.as_slice() {
+            [_, _] if fixes.iter().all(|fix| fix.is_ok()) => Err(format!(
+                "{} holds up two programs weighing {} and {}, so either could \
+                 be the wrong one",
+                self.name(parent),
+                candidates[1].1,
+                candidates[0].1
+            )),
+            _ => {
+                // The one that works, or else the first problem
+                let first_ok = fixes.iter().position(|fix| fix.is_ok()).unwrap_or(0);
+                fixes.into_iter().nth(first_ok).unwrap().map(Some)
+            }
+        }
+    }
+
+    /// Changes the weight of a program so that it and everything it holds
+    /// up weighs `total`, checking that this balances the whole tower
+    fn fix_total(&self, program: Id, total: u128) -> Result<Fix, String> {
+        let weight = self.weights[program];
+        let correct = (u128::from(weight) + total)
+            .checked_sub(self.subtree_weights[program])
+            .ok_or_else(|| format!("{} would need a negative weight", self.name(program)))?;
+        let correct = u64::try_from(correct).map_err(|_| {
+            format!(
+                "{} would need a weight above {}",
+                self.name(program),
+                u64::MAX
+            )
+        })?;
+
+        // Changing that one weight has to balance everything else too
+        let mut fixed = self.clone();
+        fixed.weights[program] = correct;
+        fixed.update_subtree_weights();
+        let mut unbalanced: Vec<&str> = (0..fixed.names.len())
+            .filter(|&id| fixed.unbalanced(id))
+            .map(|id| self.name(id))
+            .collect();
+        if !unbalanced.is_empty() {
+            unbalanced.sort();
+            return Err(format!(
+                "More than one weight is wrong: after changing {} to {}, the \
+                 programs held up by {} still don't weigh the same",
+                self.name(program),
+                correct,
+                unbalanced.join(", ")
+            ));
+        }
+
+        Ok(Fix {
+            program,
+            weight,
+            correct,
+        })
+    }
+}
+
+/// A question about the tower, asked with a subcommand
+#[derive(Debug, PartialEq)]
+enum Query {
+    Root,
+    Depth(String),
+    Path(String),
+    Lca(String, String),
+    Subtree(String),
+}
+
+impl Query {
+    /// Parses the subcommand and its arguments (everything but the input)
+    fn from_args(args: &[String]) -> Option<Query> {
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        match args.as_slice() {
+            ["root"] => Some(Query::Root),
+            ["depth", program] => Some(Query::Depth(program.to_string())),
+            ["path", program] => Some(Query::Path(program.to_string())),
+            ["lca", a, b] => Some(Query::Lca(a.to_string(), b.to_string())),
+            ["subtree", program] => Some(Query::Subtree(program.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The answer, as printed
+    fn answer(&self, tree: &Tree) -> Result<String, String> {
+        let id = |name: &str| {
+            tree.id(name)
+                .ok_or_else(|| format!("No program named '{}'", name))
+        };
+        Ok(match *self {
+            Query::Root => match tree.root() {
+                Some(root) => format!("{}\n", tree.name(root)),
+                None => return Err(String::from("The tower has no root")),
+            },
+            Query::Depth(ref program) => format!("{}\n", tree.depth(id(program)?)),
+            Query::Path(ref program) => tree
+                .path_to_root(id(program)?)
+                .into_iter()
+                .map(|id| format!("{}\n", tree.name(id)))
+                .collect(),
+            Query::Lca(ref a, ref b) => match tree.lowest_common_ancestor(id(a)?, id(b)?) {
+                Some(ancestor) => format!("{}\n", tree.name(ancestor)),
+                None => return Err(format!("{} and {} aren't in the same tower", a, b)),
+            },
+            Query::Subtree(ref program) => tree.subtree_text(id(program)?),
+        })
+    }
 }
 
 fn print_usage() {
     println!("Day 7: Recursive Circus");
     println!("Usage:");
-    println!("07 <input>");
+    println!("07 [<query>] <input>");
     println!("  <input> - Input tree (see AoC example).");
     println!("            Typically from file (e.g. \"$(cat inputfile)\"");
+    println!("            Problems with its structure are reported by line first.");
+    println!("Prints the root program, then the weight the one wrongly weighted");
+    println!("program should have to balance the tower. Or, with a query:");
+    println!("  root              - The bottom program");
+    println!("  depth <program>   - Number of programs below a program");
+    println!("  path <program>    - The programs from a program down to the root");
+    println!("  lca <a> <b>       - The highest program holding up both programs");
+    println!("  subtree <program> - A program and everything it holds up, with");
+    println!("                      weights and total weights");
 }
 
 fn main() {
-    if let (2, Some(input)) = (args().count(), args().nth(1)) {
-        println!("{}", root_node(input));
-    } else {
-        print_usage();
+    let args: Vec<String> = args().skip(1).collect();
+    let (input, query) = match args.split_last() {
+        Some((input, [])) => (input, None),
+        Some((input, query)) => match Query::from_args(query) {
+            Some(query) => (input, Some(query)),
+            None => {
+                print_usage();
+                exit(-1);
+            }
+        },
+        None => {
+            print_usage();
+            exit(-1);
+        }
+    };
+
+    let nodes = match parse_tree(input) {
+        Ok(nodes) => nodes,
+        Err(message) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+    };
+    if let Err(errors) = validate(&nodes) {
+        for error in errors {
+            println!("Error: {}", error);
+        }
         exit(-1);
     }
-}
+    let tree = Tree::new(&nodes);
 
+    if let Some(query) = query {
+        match query.answer(&tree) {
+            Ok(answer) => print!("{}", answer),
+            Err(message) => {
+                println!("Error: {}", message);
+                exit(-1);
+            }
+        }
+        return;
+    }
+
+    match tree.root() {
+        Some(root) => println!("{}", tree.name(root)),
+        None => {
+            println!("Error: The tower has no root");
+            exit(-1);
+        }
+    }
+    match tree.fix() {
+        Ok(Some(fix)) => {
+            println!("{}", fix.correct);
+            // The details go to stderr, so the answers can be piped
+            eprintln!(
+                "{} weighs {}, but should weigh {}",
+                tree.name(fix.program),
+                fix.weight,
+                fix.correct
+            );
+        }
+        Ok(None) => {
+            println!("Error: The tower is already balanced");
+            exit(-1);
+        }
+        Err(message) => {
+            println!("Error: {}", message);
+            exit(-1);
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -92,49 +689,37 @@ mod tests {
 
     #[test]
     fn node_no_child_values() {
-        let node = Node::from_str("abcd (45)");
+        let node = Node::from_str("abcd (45)").unwrap();
         assert_eq!(node.value, "abcd");
+        assert_eq!(node.weight, 45);
         assert!(node.child_values.is_empty());
-        assert_eq!(flatten(node), vec!["abcd"]);
     }
 
     #[test]
     fn node_one_child() {
-        let node = Node::from_str("abc (100) -> defg");
+        let node = Node::from_str("abc (100) -> defg").unwrap();
         assert_eq!(node.value, "abc");
         assert_eq!(node.child_values, vec!["defg"]);
     }
 
     #[test]
     fn node_multiple_child_values() {
-        let node = Node::from_str("ab (1) -> cd, efg, hijk");
+        let node = Node::from_str("ab (1) -> cd, efg, hijk").unwrap();
         assert_eq!(node.value, "ab");
         assert_eq!(node.child_values, vec!["cd", "efg", "hijk"]);
     }
 
-    #[test]
-    fn flatten_no_child_values() {
-        let node = Node {
-            value: "abcd",
-            child_values: vec![],
-        };
-        assert_eq!(flatten(node), vec!["abcd"]);
-    }
-
-    #[test]
-    fn flatten_with_child_values() {
-        let node = Node {
-            value: "ab",
-            child_values: vec!["cd", "ef"],
-        };
-        assert_eq!(flatten(node), vec!["cd", "ef", "ab"]);
+    /// Name of the bottom program
+    fn root(tree: &str) -> String {
+        let tree = Tree::new(&parse_tree(tree).unwrap());
+        String::from(tree.name(tree.root().unwrap()))
     }
 
     #[test]
     fn two_nodes() {
         // a -> b
         let nodes = "a (5) -> b\nb (3)";
-        assert_eq!(root_node(String::from(nodes)), "a");
+        assert_eq!(root(nodes), "a");
     }
 
     #[test]
@@ -142,7 +727,371 @@ mod tests {
         // b -> (a,c)
         // c -> d
         let nodes = "b (5) -> a, c\na (1)\nd (2)\nc (4) -> d";
-        assert_eq!(root_node(String::from(nodes)), "b");
+        assert_eq!(root(nodes), "b");
+    }
+
+    const AOC_EXAMPLE: &str = "pbga (66)
+xhth (57)
+ebii (61)
+havc (66)
+ktlj (57)
+fwft (72) -> ktlj, cntj, xhth
+qoyq (66)
+padx (45) -> pbga, havc, qoyq
+tknk (41) -> ugml, padx, fwft
+jptl (61)
+ugml (68) -> gyxo, ebii, jptl
+gyxo (61)
+cntj (57)";
+
+    /// The program to fix, with its weight and the weight it should have
+    fn fix(tree: &str) -> Result<Option<(String, u64, u64)>, String> {
+        let tree = Tree::new(&parse_tree(tree).unwrap());
+        tree.fix().map(|fix| {
+            fix.map(|fix| {
+                (
+                    String::from(tree.name(fix.program)),
+                    fix.weight,
+                    fix.correct,
+                )
+            })
+        })
+    }
+
+    #[test]
+    fn node_weights() {
+        assert_eq!(Node::from_str("a (0)").unwrap().weight, 0);
+        assert_eq!(
+            Node::from_str("a (18446744073709551615) -> b")
+                .unwrap()
+                .weight,
+            u64::MAX
+        );
+        assert_eq!(
+            Node::from_str("a").err(),
+            Some(String::from("Missing weight for 'a'"))
+        );
+        assert_eq!(
+            Node::from_str("a (-1)").err(),
+            Some(String::from("Invalid weight '(-1)'"))
+        );
+        assert_eq!(
+            Node::from_str("a 12").err(),
+            Some(String::from("Invalid weight '12'"))
+        );
+        assert_eq!(
+            parse_tree("a (1) -> b\nb (x)").err(),
+            Some(String::from("Line 2: Invalid weight '(x)'"))
+        );
+    }
+
+    #[test]
+    fn aoc_example() {
+        assert_eq!(root(AOC_EXAMPLE), "tknk");
+
+        let tree = Tree::new(&parse_tree(AOC_EXAMPLE).unwrap());
+        let total = |name| tree.subtree_weights[tree.id(name).unwrap()];
+        assert_eq!(total("ugml"), 251);
+        assert_eq!(total("padx"), 243);
+        assert_eq!(total("fwft"), 243);
+        assert_eq!(total("tknk"), 778);
+        assert_eq!(fix(AOC_EXAMPLE), Ok(Some((String::from("ugml"), 68, 60))));
+    }
+
+    #[test]
+    fn deep_imbalance() {
+        // d is too heavy, which also makes b heavier than c
+        let tree = "a (1) -> b, c, e\nb (2) -> d, f, g\nc (8)\ne (8)\nd (3)\nf (2)\ng (2)";
+        assert_eq!(fix(tree), Ok(Some((String::from("d"), 3, 2))));
+
+        // With only two children, the unbalanced one is the odd one
+        let tree = "a (1) -> b, c\nb (1) -> d, e, f\nc (4)\nd (1)\ne (1)\nf (2)";
+        assert_eq!(fix(tree), Ok(Some((String::from("f"), 2, 1))));
+
+        // Below a program holding up a single program
+        let tree = "a (1) -> b\nb (2) -> c, d, e\nc (3)\nd (3)\ne (5)";
+        assert_eq!(fix(tree), Ok(Some((String::from("e"), 5, 3))));
+        let tree = "a (1) -> b, c\nb (1) -> m\nc (5)\nm (1) -> d, e, f\nd (1)\ne (1)\nf (2)";
+        assert_eq!(fix(tree), Ok(Some((String::from("f"), 2, 1))));
+
+        // Of two programs, only changing q balances the rest of the tower
+        let tree = "a (1) -> x, y, z\nx (1) -> p, q\ny (3)\nz (3)\np (1)\nq (2)";
+        assert_eq!(fix(tree), Ok(Some((String::from("q"), 2, 1))));
+    }
+
+    #[test]
+    fn balanced_tower() {
+        assert_eq!(fix("a (5) -> b, c\nb (3)\nc (3)"), Ok(None));
+        assert_eq!(fix("a (5)"), Ok(None));
+        // A program holding up a single program is always balanced
+        assert_eq!(fix("a (5) -> b\nb (3)"), Ok(None));
+    }
+
+    #[test]
+    fn ambiguous_imbalance() {
+        assert_eq!(
+            fix("a (5) -> b, c\nb (3)\nc (4)"),
+            Err(String::from(
+                "a holds up two programs weighing 3 and 4, so either could be the wrong one"
+            ))
+        );
+        assert_eq!(
+            fix("a (5) -> b, c, d, e\nb (1)\nc (1)\nd (2)\ne (2)"),
+            Err(String::from(
+                "a holds up 2 programs weighing 1 and 2 weighing 2, so there's no \
+                 single odd program"
+            ))
+        );
+    }
+
+    #[test]
+    fn several_imbalances() {
+        // Three different weights under a
+        assert_eq!(
+            fix("a (1) -> b, c, d\nb (1)\nc (2)\nd (3)"),
+            Err(String::from(
+                "More than one weight is wrong: the programs held up by a have more \
+                 than two different weights"
+            ))
+        );
+
+        // Two wrong weights in different parts of the tower: e is too
+        // heavy, and d is too light
+        let tree = "a (1) -> b, c, d\nb (4) -> e, f, g\nc (2) -> h, i, j\nd (7)\n\
+                    e (2)\nf (1)\ng (1)\nh (2)\ni (2)\nj (2)";
+        assert_eq!(
+            fix(tree),
+            Err(String::from(
+                "More than one weight is wrong: after changing e to 1, the \
+                 programs held up by a still don't weigh the same"
+            ))
+        );
+
+        // Fixing the tower would need a negative weight, or one too big
+        // for a u64
+        assert_eq!(
+            fix("a (1) -> b, c, d\nb (1)\nc (9)\nd (9)"),
+            Ok(Some((String::from("b"), 1, 9)))
+        );
+        assert_eq!(
+            fix("a (1) -> b, c, d\nb (1) -> e\nc (0)\nd (0)\ne (1)"),
+            Err(String::from("b would need a negative weight"))
+        );
+        let tree = "a (1) -> b, c, d\nb (1) -> e, f\nc (1) -> g, h\nd (5)\n\
+                    e (18446744073709551615)\nf (18446744073709551615)\n\
+                    g (18446744073709551615)\nh (18446744073709551615)";
+        assert_eq!(
+            fix(tree),
+            Err(String::from(
+                "d would need a weight above 18446744073709551615"
+            ))
+        );
+    }
+
+    #[test]
+    fn huge_weights() {
+        // The total weight of the tower doesn't fit in a u64
+        let tree = "a (1) -> b, c, d\nb (18446744073709551615)\nc (1)\nd (1)";
+        assert_eq!(
+            Tree::new(&parse_tree(tree).unwrap()).subtree_weights[0],
+            u128::from(u64::MAX) + 3
+        );
+        assert_eq!(fix(tree), Ok(Some((String::from("b"), u64::MAX, 1))));
+    }
+
+    #[test]
+    fn tree_structure() {
+        let tree = Tree::new(&parse_tree(AOC_EXAMPLE).unwrap());
+        assert_eq!(tree.names.len(), 13);
+        let id = |name| tree.id(name).unwrap();
+
+        // Names are interned in the order they first appear
+        assert_eq!(id("pbga"), 0);
+        assert_eq!(tree.name(id("cntj")), "cntj");
+        assert_eq!(tree.id("nope"), None);
+
+        assert_eq!(tree.root(), Some(id("tknk")));
+        assert_eq!(tree.parents[id("tknk")], None);
+        assert_eq!(tree.parents[id("gyxo")], Some(id("ugml")));
+        assert_eq!(
+            tree.children[id("tknk")],
+            vec![id("ugml"), id("padx"), id("fwft")]
+        );
+        assert_eq!(tree.weights[id("ugml")], 68);
     }
 
+    #[test]
+    fn tree_queries() {
+        let tree = Tree::new(&parse_tree(AOC_EXAMPLE).unwrap());
+        let id = |name| tree.id(name).unwrap();
+
+        assert_eq!(tree.depth(id("tknk")), 0);
+        assert_eq!(tree.depth(id("padx")), 1);
+        assert_eq!(tree.depth(id("gyxo")), 2);
+        assert_eq!(
+            tree.path_to_root(id("gyxo")),
+            vec![id("gyxo"), id("ugml"), id("tknk")]
+        );
+
+        let lca = |a, b| {
+            tree.lowest_common_ancestor(id(a), id(b))
+                .map(|id| tree.name(id))
+        };
+        assert_eq!(lca("gyxo", "ebii"), Some("ugml"));
+        assert_eq!(lca("gyxo", "pbga"), Some("tknk"));
+        assert_eq!(lca("ugml", "gyxo"), Some("ugml"));
+        assert_eq!(lca("qoyq", "qoyq"), Some("qoyq"));
+
+        assert_eq!(
+            tree.subtree(id("ugml")),
+            vec![
+                (id("ugml"), 0),
+                (id("gyxo"), 1),
+                (id("ebii"), 1),
+                (id("jptl"), 1)
+            ]
+        );
+        assert_eq!(
+            tree.subtree_text(id("padx")),
+            "padx (45), total 243
+  pbga (66), total 66
+  havc (66), total 66
+  qoyq (66), total 66
+"
+        );
+    }
+
+    #[test]
+    fn query_subcommands() {
+        let tree = Tree::new(&parse_tree(AOC_EXAMPLE).unwrap());
+        let query = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            Query::from_args(&args)
+        };
+
+        assert_eq!(query(&["root"]), Some(Query::Root));
+        assert_eq!(query(&["depth"]), None);
+        assert_eq!(query(&["lca", "a"]), None);
+        assert_eq!(query(&["height", "a"]), None);
+
+        let answer = |args: &[&str]| query(args).unwrap().answer(&tree);
+        assert_eq!(answer(&["root"]), Ok(String::from("tknk\n")));
+        assert_eq!(answer(&["depth", "jptl"]), Ok(String::from("2\n")));
+        assert_eq!(
+            answer(&["path", "ktlj"]),
+            Ok(String::from("ktlj\nfwft\ntknk\n"))
+        );
+        assert_eq!(answer(&["lca", "havc", "qoyq"]), Ok(String::from("padx\n")));
+        assert_eq!(
+            answer(&["subtree", "cntj"]),
+            Ok(String::from("cntj (57), total 57\n"))
+        );
+        assert_eq!(
+            answer(&["depth", "nope"]),
+            Err(String::from("No program named 'nope'"))
+        );
+    }
+
+    fn problems(tree: &str) -> Vec<String> {
+        match validate(&parse_tree(tree).unwrap()) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_trees() {
+        assert!(problems(AOC_EXAMPLE).is_empty());
+        assert!(problems("a (1)").is_empty());
+        // Blank lines are skipped, but still count for line numbers
+        let nodes = parse_tree("\na (1) -> b\n  \nb (2)\n").unwrap();
+        assert_eq!(
+            nodes.iter().map(|&(line, _)| line).collect::<Vec<usize>>(),
+            vec![2, 4]
+        );
+        assert_eq!(validate(&nodes), Ok(()));
+    }
+
+    #[test]
+    fn empty_tree() {
+        assert_eq!(
+            validate(&parse_tree("").unwrap()),
+            Err(vec![TreeError::Empty])
+        );
+        assert_eq!(problems(" \n\n"), vec!["The tower is empty"]);
+    }
+
+    #[test]
+    fn undefined_child() {
+        assert_eq!(
+            validate(&parse_tree("a (1) -> b, c\nb (1)").unwrap()),
+            Err(vec![TreeError::Undefined {
+                program: String::from("c"),
+                parent: String::from("a"),
+                line: 1,
+            }])
+        );
+        assert_eq!(
+            problems("a (1) -> b\nb (1) -> c"),
+            vec!["Line 2: b holds up c, which is never defined"]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        assert_eq!(
+            problems("a (1) -> b\nb (1)\nb (2)"),
+            vec!["Line 3: b is already defined on line 2"]
+        );
+        assert_eq!(
+            problems("a (1) -> b, c\nb (1) -> c\nc (1)"),
+            vec!["Line 2: c is already held up on line 1"]
+        );
+        // A duplicated root is only one root
+        assert_eq!(
+            problems("a (1) -> b\nb (1)\na (1)"),
+            vec!["Line 3: a is already defined on line 1"]
+        );
+    }
+
+    #[test]
+    fn multiple_roots() {
+        assert_eq!(
+            validate(&parse_tree("a (1) -> b\nb (1)\nc (1)").unwrap()),
+            Err(vec![TreeError::MultipleRoots {
+                roots: vec![(String::from("a"), 1), (String::from("c"), 3)],
+            }])
+        );
+        assert_eq!(
+            problems("a (1)\nb (1)"),
+            vec!["More than one program is at the bottom: a (line 1), b (line 2)"]
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        // No root at all
+        assert_eq!(
+            problems("a (1) -> b\nb (1) -> a"),
+            vec!["Programs hold themselves up: a (line 1), b (line 2)"]
+        );
+        assert_eq!(
+            problems("a (1) -> a"),
+            vec!["Programs hold themselves up: a (line 1)"]
+        );
+
+        // A cycle off to the side of the tree...
+        assert_eq!(
+            problems("a (1) -> b\nb (1)\nd (1) -> e\ne (1) -> d"),
+            vec!["Programs hold themselves up: d (line 3), e (line 4)"]
+        );
+        // ...and a cycle back into the tree gives a program two parents
+        assert_eq!(
+            problems("a (1) -> b\nb (1) -> c\nc (1) -> b"),
+            vec![
+                "Line 3: b is already held up on line 1",
+                "Programs hold themselves up: b (line 2), c (line 3)",
+            ]
+        );
+    }
 }
@@ -31,13 +31,13 @@ impl Operator {
     where
         T: PartialEq + PartialOrd,
     {
-        match self {
-            &Operator::EQ => a.eq(b),
-            &Operator::NE => a.ne(b),
-            &Operator::LT => a.lt(b),
-            &Operator::GT => a.gt(b),
-            &Operator::GE => a.ge(b),
-            &Operator::LE => a.le(b),
+        match *self {
+            Operator::EQ => a.eq(b),
+            Operator::NE => a.ne(b),
+            Operator::LT => a.lt(b),
+            Operator::GT => a.gt(b),
+            Operator::GE => a.ge(b),
+            Operator::LE => a.le(b),
         }
     }
 }
@@ -60,9 +60,9 @@ impl Opcode {
     where
         T: AddAssign + SubAssign,
     {
-        match self {
-            &Opcode::Increment => reg.add_assign(val),
-            &Opcode::Decrement => reg.sub_assign(val),
+        match *self {
+            Opcode::Increment => reg.add_assign(val),
+            Opcode::Decrement => reg.sub_assign(val),
         };
     }
 }
@@ -100,8 +100,8 @@ where
         {
             Some(Instruction {
                 register: String::from(symbols[0]),
-                op: op,
-                operand: operand,
+                op,
+                operand,
                 condition_register: String::from(symbols[4]),
                 condition: cond_op,
                 condition_operand: cond_operand,
@@ -116,7 +116,7 @@ fn register_dump(input: String) -> HashMap<String, i32> {
     let mut registers: HashMap<String, i32> = HashMap::new();
 
     for line in input.lines() {
-        if let Some(mut instruction) = Instruction::<i32>::from_line(&line) {
+        if let Some(instruction) = Instruction::<i32>::from_line(line) {
             let condition_reg_val: i32 =
                 *registers.entry(instruction.condition_register).or_insert(0);
             let condition_operand = instruction.condition_operand;
@@ -32,21 +32,21 @@ fn score(mut stream: Chars) -> u32 {
     while let Some(ch) = stream.next() {
 
         if !in_garbage {
-            match &ch {
-                &'{' => {
+            match ch {
+                '{' => {
                     total_score += next_group_score;
                     next_group_score += 1;
                 },
-                &'}' => next_group_score -= 1,
-                &'<' => in_garbage = true,
-                &_ => {},
+                '}' => next_group_score -= 1,
+                '<' => in_garbage = true,
+                _ => {},
             };
         }
         else {
-            match &ch {
-                &'!' => {stream.next();},
-                &'>' => in_garbage = false,
-                &_ => {},
+            match ch {
+                '!' => {stream.next();},
+                '>' => in_garbage = false,
+                _ => {},
             };
         };
     };
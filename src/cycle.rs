@@ -0,0 +1,230 @@
+//! Cycle detection for deterministic state machines: starting from an
+//! initial state, a step function is applied until a state repeats.
+//!
+//! Since each state is fully determined by the one before it, the sequence
+//! of states is a (possibly empty) transient followed by a loop that
+//! repeats forever. detect() finds the length of both.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// Algorithm used to find the cycle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// Remembers every state seen. Each state is only stepped once, but
+    /// memory grows with the number of states before the repeat.
+    HashSet,
+    /// Brent's algorithm: keeps a constant number of states around and
+    /// steps each state at most about three times.
+    Brent,
+    /// Floyd's (tortoise and hare) algorithm: keeps a constant number of
+    /// states around, but usually takes more steps than Brent's.
+    Floyd,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Method, String> {
+        match s {
+            "hash" => Ok(Method::HashSet),
+            "brent" => Ok(Method::Brent),
+            "floyd" => Ok(Method::Floyd),
+            _ => Err(format!("Unknown cycle detection method: {}", s)),
+        }
+    }
+}
+
+/// The loop found in a sequence of states
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle<S> {
+    /// Number of steps before the first state of the loop (mu)
+    pub start: u64,
+    /// Number of steps around the loop (lambda)
+    pub length: u64,
+    /// The first state of the loop
+    pub entry: S,
+    /// Every state in the loop, starting with `entry` (only if requested)
+    pub states: Option<Vec<S>>,
+}
+
+/// Steps from `initial` until a state repeats, returning the loop. The
+/// step function updates a state in place. If `keep_states` is set, the
+/// states in the loop are collected too (which takes `length` extra steps).
+pub fn detect<S, F>(initial: S, mut step: F, method: Method, keep_states: bool) -> Cycle<S>
+where
+    S: Clone + Eq + Hash,
+    F: FnMut(&mut S),
+{
+    let (start, length, entry) = match method {
+        Method::HashSet => hash_set(initial, &mut step),
+        Method::Brent => brent(initial, &mut step),
+        Method::Floyd => floyd(initial, &mut step),
+    };
+
+    let states = if keep_states {
+        let mut state = entry.clone();
+        let mut states = Vec::new();
+        for _ in 0..length {
+            states.push(state.clone());
+            step(&mut state);
+        }
+        Some(states)
+    } else {
+        None
+    };
+
+    Cycle {
+        start,
+        length,
+        entry,
+        states,
+    }
+}
+
+/// Returns (start, length, entry) for each of the methods below
+type Found<S> = (u64, u64, S);
+
+fn hash_set<S, F>(mut state: S, step: &mut F) -> Found<S>
+where
+    S: Clone + Eq + Hash,
+    F: FnMut(&mut S),
+{
+    // Each state is mapped to the step it was first seen on
+    let mut first_seen = HashMap::new();
+    let mut steps = 0;
+    loop {
+        if let Some(first) = first_seen.insert(state.clone(), steps) {
+            return (first, steps - first, state);
+        }
+        step(&mut state);
+        steps += 1;
+    }
+}
+
+fn brent<S, F>(initial: S, step: &mut F) -> Found<S>
+where
+    S: Clone + Eq,
+    F: FnMut(&mut S),
+{
+    // Find the loop length: the hare moves one state at a time, and the
+    // tortoise teleports to it whenever the number of steps since the
+    // last teleport reaches the next power of two.
+    let mut tortoise = initial.clone();
+    let mut hare = initial.clone();
+    step(&mut hare);
+    let (mut power, mut length) = (1, 1);
+    while tortoise != hare {
+        if power == length {
+            tortoise = hare.clone();
+            power *= 2;
+            length = 0;
+        }
+        step(&mut hare);
+        length += 1;
+    }
+
+    // Find the start of the loop: with the hare `length` steps ahead of
+    // the tortoise, they first meet at the start of the loop.
+    tortoise = initial.clone();
+    hare = initial;
+    for _ in 0..length {
+        step(&mut hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        start += 1;
+    }
+
+    (start, length, tortoise)
+}
+
+fn floyd<S, F>(initial: S, step: &mut F) -> Found<S>
+where
+    S: Clone + Eq,
+    F: FnMut(&mut S),
+{
+    // The hare moves twice as fast as the tortoise, so they meet inside
+    // the loop once the tortoise has taken a multiple of the loop length
+    // steps.
+    let mut tortoise = initial.clone();
+    let mut hare = initial.clone();
+    loop {
+        step(&mut tortoise);
+        step(&mut hare);
+        step(&mut hare);
+        if tortoise == hare {
+            break;
+        }
+    }
+
+    // Find the start of the loop: the tortoise starts over, and moving both
+    // one step at a time, they meet at the start of the loop.
+    tortoise = initial;
+    let mut start = 0;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        start += 1;
+    }
+
+    // Find the loop length by going around it once
+    hare = tortoise.clone();
+    step(&mut hare);
+    let mut length = 1;
+    while tortoise != hare {
+        step(&mut hare);
+        length += 1;
+    }
+
+    (start, length, tortoise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [Method; 3] = [Method::HashSet, Method::Brent, Method::Floyd];
+
+    #[test]
+    fn transient_and_loop() {
+        // 0 -> 1 -> 2 -> 3 -> 4 -> 5 -> 2 -> ...
+        for &method in &METHODS {
+            let cycle = detect(0, |x| *x = if *x == 5 { 2 } else { *x + 1 }, method, true);
+            assert_eq!(
+                cycle,
+                Cycle {
+                    start: 2,
+                    length: 4,
+                    entry: 2,
+                    states: Some(vec![2, 3, 4, 5]),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_point() {
+        for &method in &METHODS {
+            let cycle = detect(7, |_| {}, method, false);
+            assert_eq!((cycle.start, cycle.length, cycle.entry), (0, 1, 7));
+            assert_eq!(cycle.states, None);
+        }
+    }
+
+    #[test]
+    fn methods_agree() {
+        // x -> x^2 + 1 (mod m) has transients and loops of all sizes
+        for m in 1..200u64 {
+            let cycles: Vec<Cycle<u64>> = METHODS
+                .iter()
+                .map(|&method| detect(0, |x| *x = (*x * *x + 1) % m, method, true))
+                .collect();
+            assert_eq!(cycles[0], cycles[1]);
+            assert_eq!(cycles[0], cycles[2]);
+        }
+    }
+}
@@ -0,0 +1,4 @@
+//! Code shared between the solutions in src/bin
+
+pub mod cycle;
+pub mod hash;